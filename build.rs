@@ -3,41 +3,71 @@
 struct LangDir {
     lang_name: &'static str,
     path: &'static str,
-    scanner_name: &'static str,
+    /// Name of the external scanner file, for grammars with an external scanner
+    scanner_name: Option<&'static str>,
     scanner_cplusplus: bool,
 }
 
 static OCAML_LANG: LangDir = LangDir {
     lang_name: "ocaml",
     path: "parsers/ocaml/ocaml/src",
-    scanner_name: "scanner.cc",
+    scanner_name: Some("scanner.cc"),
+    scanner_cplusplus: true,
+};
+
+static OCAML_INTERFACE_LANG: LangDir = LangDir {
+    lang_name: "ocaml_interface",
+    path: "parsers/ocaml/interface/src",
+    scanner_name: Some("scanner.cc"),
     scanner_cplusplus: true,
 };
 
 static RUST_LANG: LangDir = LangDir {
     lang_name: "rust",
     path: "parsers/rust/src",
-    scanner_name: "scanner.c",
+    scanner_name: Some("scanner.c"),
     scanner_cplusplus: false,
 };
 
 static DART_LANG: LangDir = LangDir {
     lang_name: "dart",
     path: "parsers/dart/src",
-    scanner_name: "scanner.c",
+    scanner_name: Some("scanner.c"),
     scanner_cplusplus: false,
 };
 
 static JS_LANG: LangDir = LangDir {
     lang_name: "js",
     path: "parsers/js/src",
-    scanner_name: "scanner.c",
+    scanner_name: Some("scanner.c"),
+    scanner_cplusplus: false,
+};
+
+static TS_LANG: LangDir = LangDir {
+    lang_name: "typescript",
+    path: "parsers/typescript/typescript/src",
+    scanner_name: Some("scanner.c"),
+    scanner_cplusplus: false,
+};
+
+static TSX_LANG: LangDir = LangDir {
+    lang_name: "tsx",
+    path: "parsers/typescript/tsx/src",
+    scanner_name: Some("scanner.c"),
+    scanner_cplusplus: false,
+};
+
+static PYTHON_LANG: LangDir = LangDir {
+    lang_name: "python",
+    path: "parsers/python/src",
+    scanner_name: Some("scanner.c"),
     scanner_cplusplus: false,
 };
 
 impl LangDir {
-    fn scanner_path(&self) -> String {
-        format!("{}/{}", self.path, self.scanner_name)
+    fn scanner_path(&self) -> Option<String> {
+        self.scanner_name
+            .map(|scanner_name| format!("{}/{}", self.path, scanner_name))
     }
 
     fn parser_path(&self) -> String {
@@ -45,14 +75,29 @@ impl LangDir {
     }
 }
 
-static LANGS: [&LangDir; 4] = [&OCAML_LANG, &RUST_LANG, &DART_LANG, &JS_LANG];
+static GO_LANG: LangDir = LangDir {
+    lang_name: "go",
+    path: "parsers/go/src",
+    scanner_name: None,
+    scanner_cplusplus: false,
+};
+
+static LANGS: [&LangDir; 9] = [
+    &OCAML_LANG,
+    &OCAML_INTERFACE_LANG,
+    &RUST_LANG,
+    &DART_LANG,
+    &JS_LANG,
+    &TS_LANG,
+    &TSX_LANG,
+    &PYTHON_LANG,
+    &GO_LANG,
+];
 
 fn main() {
     for lang in LANGS.iter() {
-        let scanner_path = lang.scanner_path();
         let parser_path = lang.parser_path();
 
-        println!("cargo:rerun-if-changed={}", scanner_path);
         println!("cargo:rerun-if-changed={}", parser_path);
 
         cc::Build::new()
@@ -61,12 +106,16 @@ fn main() {
             .warnings(false)
             .compile(&format!("{}_parser", lang.lang_name));
 
-        cc::Build::new()
-            .include(lang.path)
-            .file(scanner_path)
-            .cpp(lang.scanner_cplusplus)
-            .warnings(false)
-            .compile(&format!("{}_scanner", lang.lang_name));
+        if let Some(scanner_path) = lang.scanner_path() {
+            println!("cargo:rerun-if-changed={}", scanner_path);
+
+            cc::Build::new()
+                .include(lang.path)
+                .file(scanner_path)
+                .cpp(lang.scanner_cplusplus)
+                .warnings(false)
+                .compile(&format!("{}_scanner", lang.lang_name));
+        }
     }
 
     let hash = rustc_tools_util::get_commit_hash().unwrap_or_default();
@@ -0,0 +1,2 @@
+; Names of function definitions
+(function_item name: (identifier) @name)
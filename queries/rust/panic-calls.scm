@@ -0,0 +1,4 @@
+; Uses of panic!, todo!, unimplemented!, and unreachable!
+(macro_invocation
+  macro: (identifier) @macro
+  (#match? @macro "^(panic|todo|unimplemented|unreachable)$"))
@@ -0,0 +1,3 @@
+; Comments with TODO, FIXME, or XXX
+([(line_comment) (block_comment)] @comment
+  (#match? @comment "TODO|FIXME|XXX"))
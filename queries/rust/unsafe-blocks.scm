@@ -0,0 +1,2 @@
+; `unsafe` blocks
+(unsafe_block) @unsafe
@@ -0,0 +1,100 @@
+//! Caching syntax trees of searched files (`--cache`)
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use tree_sitter::Tree;
+
+/// Number of files kept in the cache with `--cache`
+pub(crate) const TREE_CACHE_CAPACITY: usize = 100;
+
+/// Contents and syntax trees of recently searched files, keyed by path and modification time. When
+/// the cache is full the least recently used file is evicted.
+pub(crate) struct TreeCache {
+    capacity: usize,
+    // Ordered from the least recently used to the most recently used
+    entries: Mutex<Vec<Entry>>,
+}
+
+struct Entry {
+    path: PathBuf,
+    modified: SystemTime,
+    contents: Arc<str>,
+    tree: Tree,
+}
+
+impl TreeCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        TreeCache {
+            capacity,
+            entries: Mutex::new(Vec::with_capacity(capacity)),
+        }
+    }
+
+    /// Contents and syntax tree of the file at `path`, if the file is in the cache and was not
+    /// modified since it was added
+    pub(crate) fn get(&self, path: &Path, modified: SystemTime) -> Option<(Arc<str>, Tree)> {
+        let mut entries = self.entries.lock().unwrap();
+        let idx = entries
+            .iter()
+            .position(|entry| entry.path == path && entry.modified == modified)?;
+        let entry = entries.remove(idx);
+        let ret = (entry.contents.clone(), entry.tree.clone());
+        entries.push(entry);
+        Some(ret)
+    }
+
+    /// Add the file at `path` to the cache, replacing older versions of the file
+    pub(crate) fn insert(&self, path: &Path, modified: SystemTime, contents: Arc<str>, tree: Tree) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry| entry.path != path);
+        if entries.len() == self.capacity {
+            entries.remove(0);
+        }
+        entries.push(Entry {
+            path: path.to_owned(),
+            modified,
+            contents,
+            tree,
+        });
+    }
+}
+
+#[test]
+fn test_tree_cache() {
+    use std::time::Duration;
+
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(unsafe { crate::tree_sitter_rust() })
+        .unwrap();
+    let contents: Arc<str> = "fn f() {}".into();
+    let tree = parser.parse(contents.as_bytes(), None).unwrap();
+
+    let t0 = SystemTime::UNIX_EPOCH;
+    let t1 = t0 + Duration::from_secs(1);
+
+    let cache = TreeCache::new(2);
+    cache.insert(Path::new("a.rs"), t0, contents.clone(), tree.clone());
+    cache.insert(Path::new("b.rs"), t0, contents.clone(), tree.clone());
+    assert!(cache.get(Path::new("a.rs"), t0).is_some());
+
+    // Modified files are not returned
+    assert!(cache.get(Path::new("a.rs"), t1).is_none());
+
+    // `b.rs` is the least recently used file
+    cache.insert(Path::new("c.rs"), t0, contents.clone(), tree.clone());
+    assert!(cache.get(Path::new("b.rs"), t0).is_none());
+    assert!(cache.get(Path::new("a.rs"), t0).is_some());
+    assert!(cache.get(Path::new("c.rs"), t0).is_some());
+
+    // Newer versions of a file replace the old ones
+    cache.insert(Path::new("a.rs"), t1, contents, tree);
+    assert!(cache.get(Path::new("a.rs"), t0).is_none());
+    assert!(cache.get(Path::new("a.rs"), t1).is_some());
+    assert!(cache.get(Path::new("c.rs"), t0).is_some());
+}
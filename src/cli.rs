@@ -4,29 +4,182 @@ use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg
 
 #[derive(Debug)]
 pub(crate) struct Args<'a> {
-    pub(crate) pattern: String,
-    pub(crate) path: Option<String>,
+    /// Patterns to search, from `PATTERN` or `-e`
+    pub(crate) patterns: Vec<String>,
+    /// Files to read more patterns from, one per line
+    pub(crate) pattern_files: Vec<String>,
+    /// Files or directories to search, "-" for stdin. Empty when not specified.
+    pub(crate) paths: Vec<String>,
     /// Show column number
     pub(crate) column: bool,
+    /// Print byte offsets of matches
+    pub(crate) byte_offset: bool,
+    /// Print names of the definitions containing the matches
+    pub(crate) context_symbol: bool,
+    /// Number of columns to count for a tab character in column numbers
+    pub(crate) tab_width: usize,
     /// Don't group matches by files
     pub(crate) nogroup: bool,
-    /// Colored output
-    pub(crate) nocolor: bool,
+    /// Print file names once before matches of the file, instead of in each match
+    pub(crate) heading: bool,
+    /// Print line numbers of matched and context lines
+    pub(crate) line_number: bool,
+    /// Print matches as newline-delimited JSON
+    pub(crate) json: bool,
+    /// With `json`, print each JSON object on multiple lines
+    pub(crate) json_pretty: bool,
+    /// Print each match on a separate line as `path:line:column:line`
+    pub(crate) vimgrep: bool,
+    /// Print a NUL byte after file paths
+    pub(crate) null: bool,
+    /// Replace matches with this string
+    pub(crate) replace: Option<String>,
+    /// Print a diff instead of replacing matches
+    pub(crate) dry_run: bool,
+    /// Print matched lines with the replacements instead of replacing matches
+    pub(crate) preview: bool,
+    /// Print lines without matches
+    pub(crate) invert_match: bool,
+    /// Only search names of function definitions
+    pub(crate) def: bool,
+    /// Only print paths of files with matches
+    pub(crate) files_with_matches: bool,
+    /// Only print paths of files without matches
+    pub(crate) files_without_matches: bool,
+    /// Only print number of matches in each file
+    pub(crate) count: bool,
+    /// With `count`, also print files without matches
+    pub(crate) count_zero: bool,
+    /// Only print number of matches in each matched line
+    pub(crate) count_per_line: bool,
+    /// Maximum number of matches to report in a file
+    pub(crate) max_count: Option<usize>,
+    /// Maximum number of matches to report in all files
+    pub(crate) max_total_matches: Option<usize>,
+    /// Number of lines to print before matched lines
+    pub(crate) before_context: usize,
+    /// Number of lines to print after matched lines
+    pub(crate) after_context: usize,
+    /// Node kinds to print all lines of, when they contain a match
+    pub(crate) context_node_kinds: Vec<String>,
+    /// When to color the output
+    pub(crate) color: Color,
     /// Case sensitivity
     pub(crate) casing: Casing,
     /// Only match whole words?
     pub(crate) whole_word: bool,
+    /// Is the pattern a regex?
+    pub(crate) regex: bool,
+    /// Also search lines for matches spanning multiple tokens
+    pub(crate) text_fallback: bool,
+    /// Match characters of the patterns in order, not necessarily contiguously
+    pub(crate) fuzzy: bool,
     /// tree-sitter node kinds. When specified only search the pattern in these kinds of nodes.
     pub(crate) node_kinds: NodeKinds,
     /// Rest of the matches (`--rust`, `--ocaml` etc.)
     pub(crate) matches: ArgMatches<'a>,
     /// Ignored files or directores (patterns)
     pub(crate) ignores: Vec<String>,
+    /// Globs of files to search. When empty all files are searched.
+    pub(crate) includes: Vec<String>,
+    /// Globs of files to skip
+    pub(crate) excludes: Vec<String>,
+    /// Globs of directories to skip
+    pub(crate) exclude_dirs: Vec<String>,
+    /// Match globs case insensitively
+    pub(crate) glob_case_insensitive: bool,
+    /// Follow symlinks to directories
+    pub(crate) follow: bool,
+    /// Search hidden files and directories
+    pub(crate) hidden: bool,
+    /// Order of the files searched in directories. `None` means the order the file system returns
+    pub(crate) sort: Option<Sort>,
+    /// Tree-sitter parsers in shared libraries to search with
+    pub(crate) parsers: Vec<ParserArg>,
+    /// Directories of tree-sitter parsers in shared libraries to search with
+    pub(crate) parser_dirs: Vec<String>,
+    /// Search binary files
+    pub(crate) text: bool,
+    /// Encoding of the files without a byte order mark, UTF-8 when not given
+    pub(crate) encoding: Option<&'static encoding_rs::Encoding>,
+    /// Maximum depth of directories to walk
+    pub(crate) max_depth: Option<usize>,
+    /// Number of threads to search files. 0 means number of CPUs.
+    pub(crate) threads: usize,
+    /// Print search statistics to stderr
+    pub(crate) stats: bool,
+    /// Print number of searched files and the current directory to stderr while searching
+    pub(crate) progress: bool,
+    /// Print total number of matches to stderr
+    pub(crate) count_matches: bool,
+    /// Exit with an error when a file could not be read or parsed
+    pub(crate) strict: bool,
+    /// Keep syntax trees of recently searched files in memory, to avoid parsing a file again
+    pub(crate) cache: bool,
+    /// Keep running after searching, and search again when the searched files change
+    pub(crate) watch: bool,
+    /// Query to search instead of `pattern`
+    pub(crate) query: Option<Query>,
+    /// With `query`, capture names and texts. Only query matches with at least one of the
+    /// captures having the given text are reported.
+    pub(crate) captures: Vec<(String, String)>,
+    /// With `query`, print all lines of the matched nodes, instead of just the first lines of the
+    /// nodes and the lines of captures
+    pub(crate) full_node: bool,
+    /// With `query`, report each capture separately, like pattern matches
+    pub(crate) each_capture: bool,
+    /// Print the files that would be searched instead of searching
+    pub(crate) list_files: bool,
+    /// Print node kinds in the files instead of searching
+    pub(crate) list_node_kinds: bool,
+    /// Print the named queries instead of searching
+    pub(crate) list_queries: bool,
+    /// Print syntax trees of the files instead of searching
+    pub(crate) debug_ast: bool,
+}
+
+/// A `--parser` argument, with the `--lang-sym` and `--ext` arguments following it
+#[derive(Debug)]
+pub(crate) struct ParserArg {
+    /// Path of the shared library
+    pub(crate) path: String,
+    /// Name of the function that returns the tree-sitter language
+    pub(crate) lang_sym: Option<String>,
+    /// Extension of the files to search with the parser
+    pub(crate) ext: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Query {
+    /// Query source given with `--qs`
+    String(String),
+    /// Name of the query given with `--qn`
+    Name(String),
+    /// Path of the query file given with `--query-file`
+    File(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Color {
+    /// Always color the output
+    Always,
+    /// Never color the output
+    Never,
+    /// Color the output when stdout is a terminal and `NO_COLOR` is not set
+    Auto,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Sort {
+    /// Sort by path
+    Path,
+    /// Sort by last modification time, oldest first
+    Modified,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub(crate) enum Casing {
-    /// Match case sensitively unless the pattern contains uppercase chars
+    /// Match each pattern case insensitively unless it contains uppercase chars
     Smart,
     /// Match case sensitively
     Sensitive,
@@ -36,12 +189,20 @@ pub(crate) enum Casing {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct NodeKinds {
-    /// Search in identifiers and keywords
+    /// Search in identifiers
     pub(crate) identifier: bool,
+    /// Search in keywords
+    pub(crate) keyword: bool,
     /// Search in string literals
     pub(crate) string: bool,
     /// Search in comments
     pub(crate) comment: bool,
+    /// Search in doc comments. Implied by `comment`.
+    pub(crate) doc_comment: bool,
+    /// Search in number literals
+    pub(crate) number: bool,
+    /// Search in character literals
+    pub(crate) char: bool,
 }
 
 pub(crate) fn parse_args_safe<'a, I, T>(args_iter: I) -> Result<Args<'a>, clap::Error>
@@ -67,7 +228,7 @@ where
         .arg(
             Arg::with_name("ocaml")
                 .long("ocaml")
-                .help("Search OCaml files"),
+                .help("Search OCaml files (.ml and .mli)"),
         )
         .arg(
             Arg::with_name("dart")
@@ -77,22 +238,140 @@ where
         .arg(
             Arg::with_name("js")
                 .long("js")
-                .help("Search JavaScript files"),
+                .help("Search JavaScript files (.js, .jsx, .mjs, and .cjs)"),
+        )
+        .arg(
+            Arg::with_name("ts")
+                .long("ts")
+                .help("Search TypeScript files"),
+        )
+        .arg(
+            Arg::with_name("python")
+                .long("python")
+                .help("Search Python files"),
+        )
+        .arg(
+            Arg::with_name("go")
+                .long("go")
+                .help("Search Go files"),
+        )
+        .arg(
+            Arg::with_name("all")
+                .long("all")
+                .help("Search files of all supported languages, skip files with other extensions"),
+        )
+        .arg(
+            Arg::with_name("parser")
+                .takes_value(true)
+                .value_name("PATH")
+                .long("parser")
+                .multiple(true)
+                .number_of_values(1)
+                .long_help(PARSER_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("parser-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .long("parser-dir")
+                .multiple(true)
+                .number_of_values(1)
+                .long_help(PARSER_DIR_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("lang-sym")
+                .takes_value(true)
+                .value_name("NAME")
+                .long("lang-sym")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("parser")
+                .help(
+                    "Name of the function that returns the language in the preceding --parser \
+                    library (tree_sitter_<language>). Found in the library's symbols by default.",
+                ),
+        )
+        .arg(
+            Arg::with_name("ext")
+                .takes_value(true)
+                .value_name("EXT")
+                .long("ext")
+                .multiple(true)
+                .number_of_values(1)
+                .requires("parser")
+                .help(
+                    "Extension of the files to search with the preceding --parser. \
+                    Defaults to the language name in the library.",
+                ),
+        )
+        .arg(
+            Arg::with_name("PATTERN")
+                .takes_value(true)
+                .required_unless_one(&[
+                    "qs",
+                    "qn",
+                    "query-file",
+                    "list-node-kinds",
+                    "debug-ast",
+                    "list-queries",
+                    "files",
+                    "dump",
+                    "regexp",
+                    "file",
+                ]),
+        )
+        .arg(
+            Arg::with_name("regexp")
+                .takes_value(true)
+                .value_name("PATTERN")
+                .long("regexp")
+                .short("e")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(PATTERNS_CONFLICTS)
+                .help(
+                    "Search PATTERN. Can be given multiple times, matches of any of the patterns \
+                    are reported. The first positional argument is a path.",
+                ),
+        )
+        .arg(
+            Arg::with_name("file")
+                .takes_value(true)
+                .value_name("FILE")
+                .long("file")
+                .short("f")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(PATTERNS_CONFLICTS)
+                .help(
+                    "Search the patterns in FILE, one per line, skipping empty lines. \
+                    Can be combined with -e. The first positional argument is a path.",
+                ),
+        )
+        .arg(
+            Arg::with_name("PATH")
+                .takes_value(true)
+                .required(false)
+                .multiple(true)
+                .help(PATH_HELP_STR),
         )
-        .arg(Arg::with_name("PATTERN").takes_value(true).required(true))
-        .arg(Arg::with_name("PATH").takes_value(true).required(false))
         .arg(
             Arg::with_name("color")
-                .takes_value(false)
+                .takes_value(true)
                 .long("color")
-                .help("Colored output (enabled by default)")
+                .value_name("WHEN")
+                .min_values(0)
+                .require_equals(true)
+                .possible_values(&["always", "never", "auto"])
+                .help(COLOR_HELP_STR)
                 .overrides_with("nocolor"),
         )
         .arg(
             Arg::with_name("nocolor")
                 .takes_value(false)
                 .long("nocolor")
-                .help("Disable colored output"),
+                .help("Disable colored output (same as --color=never)")
+                .overrides_with("color"),
         )
         .arg(
             Arg::with_name("group")
@@ -110,19 +389,252 @@ where
                 .long("nogroup")
                 .help("Print file name in each match"),
         )
+        .arg(
+            Arg::with_name("heading")
+                .takes_value(false)
+                .long("heading")
+                .help(
+                    "Print file name once before matches of the file, even with --nogroup \
+                    (enabled by default without --nogroup)",
+                )
+                .overrides_with("no-heading"),
+        )
+        .arg(
+            Arg::with_name("line-number")
+                .takes_value(false)
+                .short("n")
+                .long("line-number")
+                .help("Print line numbers (enabled by default)")
+                .overrides_with("no-line-number"),
+        )
+        .arg(
+            Arg::with_name("no-line-number")
+                .takes_value(false)
+                .short("N")
+                .long("no-line-number")
+                .conflicts_with("count-per-line")
+                .help("Don't print line numbers of matched and context lines"),
+        )
+        .arg(
+            Arg::with_name("no-heading")
+                .takes_value(false)
+                .long("no-heading")
+                .help("Print file name in each match, but keep separating files with empty lines"),
+        )
+        .arg(Arg::with_name("json").takes_value(false).long("json").help(
+            "Print each match as a JSON object on a separate line. \
+                    Colors and grouping are disabled in this mode.",
+        ))
+        .arg(
+            Arg::with_name("json-pretty")
+                .takes_value(false)
+                .long("json-pretty")
+                .requires("json")
+                .help("With --json, print each JSON object indented on multiple lines"),
+        )
+        .arg(
+            Arg::with_name("vimgrep")
+                .takes_value(false)
+                .long("vimgrep")
+                .conflicts_with_all(&["json", "before-context", "after-context", "context"])
+                .help(
+                    "Print each match on a separate line as 'path:line:column:line', \
+                    for Vim's quickfix list. Implies --column, --nogroup, and --nocolor.",
+                ),
+        )
+        .arg(
+            Arg::with_name("null")
+                .takes_value(false)
+                .short("0")
+                .long("null")
+                .conflicts_with("json")
+                .help(
+                    "Print a NUL byte after file paths, instead of the character that \
+                    normally follows them. Use with -l to pass the files to 'xargs -0'.",
+                ),
+        )
+        .arg(
+            Arg::with_name("replace")
+                .takes_value(true)
+                .value_name("REPLACEMENT")
+                .long("replace")
+                .conflicts_with_all(&[
+                    "invert-match",
+                    "files-with-matches",
+                    "files-without-matches",
+                    "count",
+                    "count-per-line",
+                    "json",
+                ])
+                .long_help(REPLACE_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .takes_value(false)
+                .long("dry-run")
+                .requires("replace")
+                .help("Print a diff of the changes instead of writing files with --replace"),
+        )
+        .arg(
+            Arg::with_name("preview")
+                .takes_value(false)
+                .long("preview")
+                .requires("replace")
+                .conflicts_with("dry-run")
+                .help(
+                    "Print matched lines with the matches and their replacements instead of \
+                    writing files with --replace",
+                ),
+        )
+        .arg(
+            Arg::with_name("invert-match")
+                .takes_value(false)
+                .long("invert-match")
+                .short("v")
+                .long_help(INVERT_MATCH_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("def")
+                .takes_value(false)
+                .long("def")
+                .conflicts_with("kind")
+                .long_help(DEF_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("files-with-matches")
+                .takes_value(false)
+                .long("files-with-matches")
+                .short("l")
+                .help("Only print paths of files with matches"),
+        )
+        .arg(
+            Arg::with_name("files-without-matches")
+                .takes_value(false)
+                .long("files-without-matches")
+                .short("L")
+                .conflicts_with("files-with-matches")
+                .help("Only print paths of files without matches"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .takes_value(false)
+                .long("count")
+                .short("c")
+                .help("Only print number of matches in each file"),
+        )
+        .arg(
+            Arg::with_name("count-zero")
+                .takes_value(false)
+                .long("count-zero")
+                .requires("count")
+                .help("Print files without matches too with --count"),
+        )
+        .arg(
+            Arg::with_name("count-per-line")
+                .takes_value(false)
+                .long("count-per-line")
+                .conflicts_with_all(&[
+                    "count",
+                    "files-with-matches",
+                    "files-without-matches",
+                    "invert-match",
+                    "json",
+                    "vimgrep",
+                ])
+                .help("Only print number of matches in each matched line, as 'path:line: count'"),
+        )
+        .arg(
+            Arg::with_name("max-count")
+                .takes_value(true)
+                .value_name("NUM")
+                .short("m")
+                .long("max-count")
+                .validator(validate_num)
+                .help("Stop searching a file after NUM matches"),
+        )
+        .arg(
+            Arg::with_name("max-total-matches")
+                .takes_value(true)
+                .value_name("NUM")
+                .long("max-total-matches")
+                .validator(validate_num)
+                .conflicts_with("replace")
+                .help("Stop searching after NUM matches in all files"),
+        )
+        .arg(
+            Arg::with_name("after-context")
+                .takes_value(true)
+                .value_name("NUM")
+                .short("A")
+                .long("after-context")
+                .validator(validate_num)
+                .help("Print NUM lines after matched lines"),
+        )
+        .arg(
+            Arg::with_name("before-context")
+                .takes_value(true)
+                .value_name("NUM")
+                .short("B")
+                .long("before-context")
+                .validator(validate_num)
+                .help("Print NUM lines before matched lines"),
+        )
+        .arg(
+            Arg::with_name("context")
+                .takes_value(true)
+                .value_name("NUM")
+                .short("C")
+                .long("context")
+                .validator(validate_num)
+                .help("Print NUM lines before and after matched lines"),
+        )
+        .arg(
+            Arg::with_name("context-node")
+                .takes_value(true)
+                .value_name("KINDS")
+                .long("context-node")
+                .long_help(CONTEXT_NODE_HELP_STR),
+        )
         .arg(
             Arg::with_name("column")
                 .takes_value(false)
                 .long("column")
                 .help("Print column numbers in results (disabled by default)"),
         )
+        .arg(
+            Arg::with_name("byte-offset")
+                .takes_value(false)
+                .short("b")
+                .long("byte-offset")
+                .help(
+                    "Print the byte offset of the first match in each matched line, after the \
+                    column number (if enabled)",
+                ),
+        )
+        .arg(
+            Arg::with_name("context-symbol")
+                .takes_value(false)
+                .long("context-symbol")
+                .long_help(CONTEXT_SYMBOL_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("tab-width")
+                .takes_value(true)
+                .value_name("NUM")
+                .long("tab-width")
+                .validator(validate_num)
+                .help(
+                    "Number of columns to count for a tab character in column numbers \
+                    (default: 1, columns are character counts)",
+                ),
+        )
         .arg(
             Arg::with_name("smart-case")
                 .takes_value(false)
                 .long("smart-case")
                 .help(
-                    "Match case insensitively unless PATTERN \
-                    contains uppercase characters (enabled by default)",
+                    "Match each pattern case insensitively unless it \
+                    contains uppercase characters (enabled by default, except with --replace)",
                 )
                 .short("S"),
         )
@@ -141,6 +653,107 @@ where
                 .number_of_values(1)
                 .help("Ignore files/directories whose names macth this pattern"),
         )
+        .arg(
+            Arg::with_name("max-depth")
+                .takes_value(true)
+                .value_name("NUM")
+                .long("max-depth")
+                .validator(validate_num)
+                .help(
+                    "Descend at most NUM directories below the searched directories. \
+                    With 0 only files in the searched directories are searched.",
+                ),
+        )
+        .arg(
+            Arg::with_name("text")
+                .takes_value(false)
+                .long("text")
+                .help("Search binary files (files with NUL bytes), which are skipped by default"),
+        )
+        .arg(
+            Arg::with_name("encoding")
+                .takes_value(true)
+                .value_name("ENCODING")
+                .long("encoding")
+                .validator(validate_encoding)
+                .help(
+                    "Encoding of the searched files, e.g. latin1 or shift_jis (default: utf-8). \
+                    Files starting with a byte order mark are read as UTF-8 or UTF-16.",
+                ),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .takes_value(false)
+                .long("hidden")
+                .help(
+                    "Search hidden files and directories (names starting with '.'), \
+                    which are skipped by default",
+                ),
+        )
+        .arg(
+            Arg::with_name("follow")
+                .takes_value(false)
+                .long("follow")
+                .help("Follow symlinks to directories"),
+        )
+        .arg(
+            Arg::with_name("sort")
+                .takes_value(true)
+                .value_name("BY")
+                .long("sort")
+                .possible_values(&["path", "modified"])
+                .help(
+                    "Search files in directories sorted by path, or by last modification time \
+                    (oldest first). By default files are searched in the order the file system \
+                    returns them.",
+                ),
+        )
+        .arg(
+            Arg::with_name("glob")
+                .takes_value(true)
+                .value_name("GLOB")
+                .short("g")
+                .long("glob")
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "Only search files matching GLOB. Matched against file names and \
+                    paths relative to the searched directory. Can be given multiple times.",
+                ),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .takes_value(true)
+                .value_name("GLOB")
+                .long("exclude")
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "Skip files matching GLOB. Matched against file names and \
+                    paths relative to the searched directory.",
+                ),
+        )
+        .arg(
+            Arg::with_name("exclude-dir")
+                .takes_value(true)
+                .value_name("GLOB")
+                .long("exclude-dir")
+                .multiple(true)
+                .number_of_values(1)
+                .help(
+                    "Skip directories matching GLOB. Matched against directory names and \
+                    paths relative to the searched directory.",
+                ),
+        )
+        .arg(
+            Arg::with_name("glob-case-insensitive")
+                .takes_value(false)
+                .long("glob-case-insensitive")
+                .help(
+                    "Match --glob, --exclude, and --exclude-dir globs case insensitively \
+                    (always enabled on Windows)",
+                ),
+        )
         .arg(
             Arg::with_name("ignore-case")
                 .takes_value(false)
@@ -155,6 +768,117 @@ where
                 .short("w")
                 .help("Only match whole words"),
         )
+        .arg(
+            Arg::with_name("text-fallback")
+                .long("text-fallback")
+                .conflicts_with_all(&[
+                    "def",
+                    "dump",
+                    "qs",
+                    "qn",
+                    "query-file",
+                    "files",
+                    "list-node-kinds",
+                    "debug-ast",
+                    "list-queries",
+                ])
+                .help(
+                    "Also search lines for matches of PATTERN spanning multiple tokens, e.g. \
+                    'a.b' or 'f(x'. Patterns of only letters, digits, and '_' always match in a \
+                    single token.",
+                ),
+        )
+        .arg(
+            Arg::with_name("fuzzy")
+                .long("fuzzy")
+                .conflicts_with_all(&[
+                    "regex",
+                    "word",
+                    "replace",
+                    "text-fallback",
+                    "dump",
+                    "qs",
+                    "qn",
+                    "query-file",
+                    "files",
+                    "list-node-kinds",
+                    "debug-ast",
+                    "list-queries",
+                ])
+                .help(
+                    "Match tokens with the characters of PATTERN in order, not necessarily \
+                    next to each other, e.g. 'pcfg' matches 'parse_config'. The shortest \
+                    matching part of each token is reported.",
+                ),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .takes_value(true)
+                .value_name("NUM")
+                .long("threads")
+                .short("j")
+                .validator(validate_num)
+                .help("Number of threads to use (default: number of CPUs)"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .takes_value(false)
+                .long("stats")
+                .help(
+                    "Print number of directories walked, files parsed, files failed to parse, \
+                    matches, and elapsed time to stderr after searching",
+                ),
+        )
+        .arg(
+            Arg::with_name("progress")
+                .takes_value(false)
+                .long("progress")
+                .help(
+                    "Print number of searched files and the current directory to stderr while \
+                    searching. Only printed when stderr is a terminal, and not with --json.",
+                ),
+        )
+        .arg(
+            Arg::with_name("count-matches")
+                .takes_value(false)
+                .long("count-matches")
+                .help("Print total number of matches to stderr after searching"),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .takes_value(false)
+                .long("strict")
+                .help(
+                    "Exit with status 2 after searching when a file could not be read or \
+                    parsed",
+                ),
+        )
+        .arg(
+            Arg::with_name("cache")
+                .takes_value(false)
+                .long("cache")
+                .help(
+                    "Keep syntax trees of recently searched files in memory, to avoid parsing \
+                    files searched multiple times again",
+                ),
+        )
+        .arg(
+            Arg::with_name("watch")
+                .takes_value(false)
+                .long("watch")
+                .conflicts_with_all(&["replace", "list-queries"])
+                .help(
+                    "Keep running after searching, and clear the screen and search again when \
+                    the searched files change",
+                ),
+        )
+        .arg(
+            Arg::with_name("regex")
+                .takes_value(false)
+                .long("regex")
+                .short("r")
+                .help("Treat PATTERN as a regular expression"),
+        )
         .arg(
             Arg::with_name("kind")
                 .takes_value(true)
@@ -163,15 +887,273 @@ where
                 .long("kind")
                 .long_help(KIND_HELP_STR),
         )
+        .arg(
+            Arg::with_name("dump")
+                .long("dump")
+                .requires("kind")
+                .conflicts_with_all(&[
+                    "word",
+                    "regex",
+                    "invert-match",
+                    "qs",
+                    "qn",
+                    "query-file",
+                    "replace",
+                    "files",
+                    "list-node-kinds",
+                    "debug-ast",
+                    "list-queries",
+                ])
+                .long_help(DUMP_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("qs")
+                .takes_value(true)
+                .value_name("QUERY")
+                .long("qs")
+                .conflicts_with_all(QUERY_CONFLICTS)
+                .help("Search matches of a tree-sitter query instead of PATTERN"),
+        )
+        .arg(
+            Arg::with_name("qn")
+                .takes_value(true)
+                .value_name("NAME")
+                .long("qn")
+                .conflicts_with("qs")
+                .conflicts_with_all(QUERY_CONFLICTS)
+                .long_help(QUERY_NAME_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("query-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .long("query-file")
+                .conflicts_with_all(&["qs", "qn"])
+                .conflicts_with_all(QUERY_CONFLICTS)
+                .help("Search matches of the tree-sitter query in FILE instead of PATTERN"),
+        )
+        .arg(
+            Arg::with_name("capture")
+                .takes_value(true)
+                .value_name("NAME=TEXT")
+                .long("capture")
+                .multiple(true)
+                .number_of_values(1)
+                .validator(validate_capture)
+                .long_help(CAPTURE_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("full-node")
+                .long("full-node")
+                .help("Print all lines of the nodes matched by the query, instead of just the first lines of the nodes and the lines with captures"),
+        )
+        .arg(
+            Arg::with_name("each-capture")
+                .long("each-capture")
+                .conflicts_with("full-node")
+                .long_help(EACH_CAPTURE_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("files")
+                .long("files")
+                .conflicts_with_all(QUERY_OUTPUT_CONFLICTS)
+                .conflicts_with_all(&[
+                    "qs",
+                    "qn",
+                    "query-file",
+                    "invert-match",
+                    "list-node-kinds",
+                    "debug-ast",
+                    "list-queries",
+                    "watch",
+                ])
+                .help(
+                    "Print the files that would be searched, without searching. \
+                    The first positional argument is a path.",
+                ),
+        )
+        .arg(
+            Arg::with_name("list-node-kinds")
+                .long("list-node-kinds")
+                .conflicts_with_all(QUERY_CONFLICTS)
+                .conflicts_with_all(QUERY_OUTPUT_CONFLICTS)
+                .conflicts_with_all(&["qs", "qn", "query-file"])
+                .long_help(LIST_NODE_KINDS_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("debug-ast")
+                .long("debug-ast")
+                .conflicts_with_all(QUERY_CONFLICTS)
+                .conflicts_with_all(QUERY_OUTPUT_CONFLICTS)
+                .conflicts_with_all(&["qs", "qn", "query-file", "list-node-kinds"])
+                .long_help(DEBUG_AST_HELP_STR),
+        )
+        .arg(
+            Arg::with_name("list-queries")
+                .long("list-queries")
+                .conflicts_with_all(&[
+                    "PATTERN",
+                    "qs",
+                    "qn",
+                    "query-file",
+                    "list-node-kinds",
+                    "debug-ast",
+                ])
+                .long_help(LIST_QUERIES_HELP_STR),
+        )
         .after_help(EXAMPLES_STR)
         .get_matches_from_safe(args_iter)?;
 
-    let pattern = m.value_of("PATTERN").unwrap().to_owned();
-    let path = m.value_of("PATH").map(|s| s.to_owned());
-    let column = m.is_present("column");
-    let nogroup = m.is_present("nogroup");
-    let nocolor = m.is_present("nocolor");
-    let whole_word = m.is_present("word");
+    let query = if let Some(query) = m.value_of("qs") {
+        Some(Query::String(query.to_owned()))
+    } else if let Some(name) = m.value_of("qn") {
+        Some(Query::Name(name.to_owned()))
+    } else {
+        m.value_of("query-file")
+            .map(|path| Query::File(path.to_owned()))
+    };
+
+    let each_capture = m.is_present("each-capture");
+    if query.is_some() && !each_capture {
+        // Without `--each-capture`, `--replace` replaces the matched nodes
+        if let Some(arg) = QUERY_OUTPUT_CONFLICTS
+            .iter()
+            .chain(&["preview"])
+            .find(|arg| **arg != "replace" && m.is_present(arg))
+        {
+            return Err(clap::Error::with_description(
+                &format!(
+                    "--{} can only be used with a query with --each-capture",
+                    arg
+                ),
+                clap::ErrorKind::ArgumentConflict,
+            ));
+        }
+    }
+
+    let mut pattern = m.value_of("PATTERN").map(|s| s.to_owned());
+    let mut patterns: Vec<String> = m
+        .values_of("regexp")
+        .map(|patterns| patterns.map(|s| s.to_owned()).collect())
+        .unwrap_or_default();
+    let pattern_files: Vec<String> = m
+        .values_of("file")
+        .map(|files| files.map(|s| s.to_owned()).collect())
+        .unwrap_or_default();
+    let mut paths: Vec<String> = m
+        .values_of("PATH")
+        .map(|paths| paths.map(|s| s.to_owned()).collect())
+        .unwrap_or_default();
+
+    let dump = m.is_present("dump");
+    let list_files = m.is_present("files");
+    let list_node_kinds = m.is_present("list-node-kinds");
+    let debug_ast = m.is_present("debug-ast");
+    let list_queries = m.is_present("list-queries");
+
+    // When searching a query, dumping tokens, printing syntax trees, or listing files there's no
+    // pattern, and with `-e` and `-f` the patterns are given with the flags. First positional
+    // argument is a path.
+    if query.is_some()
+        || dump
+        || list_files
+        || list_node_kinds
+        || debug_ast
+        || !patterns.is_empty()
+        || !pattern_files.is_empty()
+    {
+        if let Some(path) = pattern.take() {
+            paths.insert(0, path);
+        }
+    }
+    if let Some(pattern) = pattern {
+        patterns.push(pattern);
+    } else if patterns.is_empty() && pattern_files.is_empty() {
+        // Without a pattern (e.g. with `--dump`) every token matches
+        patterns.push(String::new());
+    }
+
+    let full_node = m.is_present("full-node");
+
+    // Values are validated by clap so `unwrap` below is safe
+    let captures: Vec<(String, String)> = m
+        .values_of("capture")
+        .map(|captures| {
+            captures
+                .map(|capture| {
+                    let (name, text) = capture.split_once('=').unwrap();
+                    (name.to_owned(), text.to_owned())
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // `--vimgrep` implies `--column`, `--nogroup`, `--nocolor`
+    let vimgrep = m.is_present("vimgrep");
+    let null = m.is_present("null");
+    let column = m.is_present("column") || vimgrep;
+    let byte_offset = m.is_present("byte-offset");
+    let context_symbol = m.is_present("context-symbol");
+    let tab_width: usize = m.value_of("tab-width").map_or(1, |n| n.parse().unwrap());
+    let nogroup = m.is_present("nogroup") || vimgrep;
+    let heading = if m.is_present("heading") {
+        !vimgrep
+    } else if m.is_present("no-heading") {
+        false
+    } else {
+        !nogroup
+    };
+    let line_number = !m.is_present("no-line-number");
+    let json = m.is_present("json");
+    let json_pretty = m.is_present("json-pretty");
+    let invert_match = m.is_present("invert-match");
+    let def = m.is_present("def");
+    let files_with_matches = m.is_present("files-with-matches");
+    let files_without_matches = m.is_present("files-without-matches");
+    let count = m.is_present("count");
+    let count_zero = m.is_present("count-zero");
+    let count_per_line = m.is_present("count-per-line");
+
+    // Values are validated by clap so `unwrap`s below are safe
+    let context: usize = m.value_of("context").map_or(0, |n| n.parse().unwrap());
+    let before_context: usize = m
+        .value_of("before-context")
+        .map_or(context, |n| n.parse().unwrap());
+    let after_context: usize = m
+        .value_of("after-context")
+        .map_or(context, |n| n.parse().unwrap());
+    let context_node_kinds: Vec<String> = m
+        .value_of("context-node")
+        .map(|kinds| {
+            kinds
+                .split(',')
+                .map(|kind| kind.trim().to_owned())
+                .filter(|kind| !kind.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    let max_count: Option<usize> = m.value_of("max-count").map(|n| n.parse().unwrap());
+    let max_total_matches: Option<usize> =
+        m.value_of("max-total-matches").map(|n| n.parse().unwrap());
+    let color = if m.is_present("nocolor") || vimgrep {
+        Color::Never
+    } else if !m.is_present("color") {
+        Color::Auto
+    } else {
+        match m.value_of("color") {
+            Some("never") => Color::Never,
+            Some("auto") => Color::Auto,
+            // `--color` without a value is the same as `--color=always`
+            _ => Color::Always,
+        }
+    };
+    let replace = m.value_of("replace").map(|s| s.to_owned());
+    let dry_run = m.is_present("dry-run");
+    let preview = m.is_present("preview");
+    // To avoid replacing parts of other identifiers, only replace whole words
+    let whole_word = m.is_present("word") || replace.is_some();
+    let regex = m.is_present("regex");
+    let text_fallback = m.is_present("text-fallback");
+    let fuzzy = m.is_present("fuzzy");
 
     let smart_case_pos = m.index_of("smart-case").map(|idx| (Casing::Smart, idx));
     let case_sensitive_pos = m
@@ -185,6 +1167,9 @@ where
     casing_args.sort_by_key(|arg| arg.as_ref().map(|(_, idx)| *idx));
 
     let casing = match casing_args.last().unwrap() {
+        // Renaming `foo` should not rename `Foo`, so replace case sensitively unless asked
+        // otherwise
+        None if replace.is_some() => Casing::Sensitive,
         None => Casing::Smart,
         Some((casing, _)) => *casing,
     };
@@ -193,23 +1178,39 @@ where
         Some(val) => {
             let mut kinds = NodeKinds {
                 identifier: false,
+                keyword: false,
                 comment: false,
+                doc_comment: false,
                 string: false,
+                number: false,
+                char: false,
             };
             for kind in val.trim().split(',') {
                 match kind {
                     "identifier" => {
                         kinds.identifier = true;
                     }
+                    "keyword" => {
+                        kinds.keyword = true;
+                    }
                     "comment" => {
                         kinds.comment = true;
                     }
+                    "doc_comment" => {
+                        kinds.doc_comment = true;
+                    }
                     "string" => {
                         kinds.string = true;
                     }
+                    "number" => {
+                        kinds.number = true;
+                    }
+                    "char" => {
+                        kinds.char = true;
+                    }
                     other => {
                         eprintln!(
-                            "Invalid kind: {}, expected a comma-separated list of: 'identifier', 'comment', 'string'",
+                            "Invalid kind: {}, expected a comma-separated list of: 'identifier', 'keyword', 'comment', 'doc_comment', 'string', 'number', 'char'",
                             other
                         );
                         ::std::process::exit(1);
@@ -220,8 +1221,12 @@ where
         }
         None => NodeKinds {
             identifier: true,
+            keyword: true,
             comment: false,
+            doc_comment: false,
             string: false,
+            number: false,
+            char: false,
         },
     };
 
@@ -230,20 +1235,187 @@ where
         .map(|ignore| ignore.map(|s| s.to_owned()).collect())
         .unwrap_or_default();
 
+    let includes: Vec<String> = m
+        .values_of("glob")
+        .map(|includes| includes.map(|s| s.to_owned()).collect())
+        .unwrap_or_default();
+
+    let excludes: Vec<String> = m
+        .values_of("exclude")
+        .map(|excludes| excludes.map(|s| s.to_owned()).collect())
+        .unwrap_or_default();
+
+    let exclude_dirs: Vec<String> = m
+        .values_of("exclude-dir")
+        .map(|excludes| {
+            excludes
+                .map(|s| s.trim_end_matches('/').to_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // Paths are case insensitive on Windows
+    let glob_case_insensitive = m.is_present("glob-case-insensitive") || cfg!(windows);
+
+    let follow = m.is_present("follow");
+    let hidden = m.is_present("hidden");
+    let sort = m.value_of("sort").map(|sort| match sort {
+        "path" => Sort::Path,
+        _ => Sort::Modified,
+    });
+
+    // `--lang-sym` and `--ext` apply to the `--parser` before them
+    let mut parsers: Vec<(usize, ParserArg)> = match (m.indices_of("parser"), m.values_of("parser"))
+    {
+        (Some(indices), Some(paths)) => indices
+            .zip(paths)
+            .map(|(idx, path)| {
+                (
+                    idx,
+                    ParserArg {
+                        path: path.to_owned(),
+                        lang_sym: None,
+                        ext: None,
+                    },
+                )
+            })
+            .collect(),
+        _ => vec![],
+    };
+    for arg in ["lang-sym", "ext"] {
+        if let (Some(indices), Some(values)) = (m.indices_of(arg), m.values_of(arg)) {
+            for (idx, value) in indices.zip(values) {
+                let parser = match parsers
+                    .iter_mut()
+                    .rev()
+                    .find(|(parser_idx, _)| *parser_idx < idx)
+                {
+                    Some((_, parser)) => parser,
+                    None => {
+                        return Err(clap::Error::with_description(
+                            &format!("--{} should be given after a --parser", arg),
+                            clap::ErrorKind::ArgumentConflict,
+                        ))
+                    }
+                };
+                if arg == "ext" {
+                    parser.ext = Some(value.trim_start_matches('.').to_owned());
+                } else {
+                    parser.lang_sym = Some(value.to_owned());
+                }
+            }
+        }
+    }
+    let parsers: Vec<ParserArg> = parsers.into_iter().map(|(_, parser)| parser).collect();
+    let parser_dirs: Vec<String> = m
+        .values_of("parser-dir")
+        .map(|dirs| dirs.map(|dir| dir.to_owned()).collect())
+        .unwrap_or_default();
+
+    let text = m.is_present("text");
+    // Values are validated by clap so `unwrap` below is safe
+    let encoding = m
+        .value_of("encoding")
+        .map(|label| encoding_rs::Encoding::for_label(label.as_bytes()).unwrap());
+    let max_depth: Option<usize> = m.value_of("max-depth").map(|n| n.parse().unwrap());
+    let threads: usize = m.value_of("threads").map_or(0, |n| n.parse().unwrap());
+    let stats = m.is_present("stats");
+    let progress = m.is_present("progress");
+    let count_matches = m.is_present("count-matches");
+    let strict = m.is_present("strict");
+    let cache = m.is_present("cache");
+    let watch = m.is_present("watch");
+
     Ok(Args {
-        pattern,
-        path,
+        patterns,
+        pattern_files,
+        paths,
         column,
+        byte_offset,
+        context_symbol,
+        tab_width,
         nogroup,
-        nocolor,
+        heading,
+        line_number,
+        json,
+        json_pretty,
+        vimgrep,
+        null,
+        replace,
+        dry_run,
+        preview,
+        invert_match,
+        def,
+        files_with_matches,
+        files_without_matches,
+        count,
+        count_zero,
+        count_per_line,
+        max_count,
+        max_total_matches,
+        before_context,
+        after_context,
+        context_node_kinds,
+        color,
         casing,
         whole_word,
+        regex,
+        text_fallback,
+        fuzzy,
         node_kinds,
         matches: m,
         ignores,
+        includes,
+        excludes,
+        exclude_dirs,
+        glob_case_insensitive,
+        follow,
+        hidden,
+        sort,
+        parsers,
+        parser_dirs,
+        text,
+        encoding,
+        max_depth,
+        threads,
+        stats,
+        progress,
+        count_matches,
+        strict,
+        cache,
+        watch,
+        query,
+        captures,
+        full_node,
+        each_capture,
+        list_files,
+        list_node_kinds,
+        list_queries,
+        debug_ast,
     })
 }
 
+fn validate_num(val: String) -> Result<(), String> {
+    match val.parse::<usize>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("Expected a number, found '{}'", val)),
+    }
+}
+
+fn validate_encoding(val: String) -> Result<(), String> {
+    match encoding_rs::Encoding::for_label(val.as_bytes()) {
+        Some(_) => Ok(()),
+        None => Err(format!("Unknown encoding '{}'", val)),
+    }
+}
+
+fn validate_capture(val: String) -> Result<(), String> {
+    match val.split_once('=') {
+        Some((name, _)) if !name.is_empty() => Ok(()),
+        _ => Err(format!("Expected NAME=TEXT, found '{}'", val)),
+    }
+}
+
 #[rustfmt::skip]
 static EXAMPLES_STR: &str = "\
 EXAMPLES:
@@ -254,10 +1426,161 @@ EXAMPLES:
         sg --rust fun --kind comment,string
 
     Search for 'fun' case sensitively in OCaml files in given directory or file
-        sg --ocaml fun path -s";
+        sg --ocaml fun path -s
+
+    Search for function names starting with 'test' in Rust files
+        sg --rust --regex 'test_[a-z_]+'
+
+    Search for 'fun' or 'test' in Rust files
+        sg --rust -e fun -e test
+
+    Search for 'fun' in Rust files in multiple directories and files
+        sg --rust fun src tests build.rs
+
+    Rename identifier 'fun' to 'fn_' in Rust files, print the changes without writing files
+        sg --rust fun --replace fn_ --dry-run
+
+    Search function calls in Rust files
+        sg --rust --qs '(call_expression function: (identifier) @f)'
+
+    Search for 'fun' in Rust code in stdin
+        cat file.rs | sg --rust fun -";
+
+/// Arguments that don't apply to query search
+static QUERY_CONFLICTS: &[&str] = &[
+    "kind",
+    "def",
+    "word",
+    "regex",
+    "invert-match",
+    "context-symbol",
+    "context-node",
+];
+
+/// Arguments that only apply to query search with `--each-capture`
+static QUERY_OUTPUT_CONFLICTS: &[&str] = &[
+    "replace",
+    "files-with-matches",
+    "files-without-matches",
+    "count",
+    "count-per-line",
+    "json",
+    "vimgrep",
+];
+
+/// Arguments that can't be used with `-e` and `-f`
+static PATTERNS_CONFLICTS: &[&str] = &[
+    "qs",
+    "qn",
+    "query-file",
+    "dump",
+    "files",
+    "list-node-kinds",
+    "debug-ast",
+    "list-queries",
+];
+
+#[rustfmt::skip]
+static PARSER_DIR_HELP_STR: &str = "\
+Search files with the tree-sitter parsers in the shared libraries (.so, .dylib, and .dll files) in directory DIR, as if each was given with --parser. Files of all of these languages are searched, as with --all.
+
+Files are searched with a parser when their extension is the language name (e.g. 'c' for a library with a tree_sitter_c function). Other extensions can be listed in an 'sg-extensions' file in DIR, with a language name followed by its extensions on each line:
+
+    c c h
+    cpp cpp hpp cc";
+
+#[rustfmt::skip]
+static PARSER_HELP_STR: &str = "\
+Search files with the tree-sitter parser in shared library PATH (.so, .dylib, or .dll), for languages sg doesn't come with. Files with the extension given with --ext after --parser are searched with the parser. Can be given multiple times to search languages of multiple parsers.
+
+Node kinds of the language are not known, all named leaf nodes are searched as identifiers, and words like 'if' as keywords. --kind string, comment etc. do not find anything.
+
+Example: sg --parser libtree-sitter-c.so --ext c --parser libtree-sitter-java.so --ext java PATTERN";
+
+#[rustfmt::skip]
+static COLOR_HELP_STR: &str = "\
+When to color the output: 'always', 'never', or 'auto'. 'auto' (the default) colors the output when stdout is a terminal and the NO_COLOR environment variable is not set. --color without a value is the same as --color=always.";
+
+#[rustfmt::skip]
+static CONTEXT_NODE_HELP_STR: &str = "\
+Print all lines of the innermost node containing a match with one of the comma-separated node KINDS as context, in addition to the lines printed with -A, -B, -C. For example, with --context-node call_expression a match in an argument of a call spanning multiple lines is printed with all lines of the call.
+
+Use --debug-ast to find the node kinds of a language.
+
+Example: sg --rust x --context-node call_expression,let_declaration";
+
+#[rustfmt::skip]
+static CONTEXT_SYMBOL_HELP_STR: &str = "\
+Print the name of the innermost definition (function, impl, trait, module, class etc.) containing the first match of each matched line, after the line number, column number, and byte offset (if enabled). Nothing is printed for matches outside of definitions.
+
+Example: sg --rust TODO -k comment --context-symbol";
+
+#[rustfmt::skip]
+static CAPTURE_HELP_STR: &str = "\
+Only report query matches where the text of the capture NAME is TEXT. TEXT is matched with smart case, like patterns (see -S, -s, -i). Can be given multiple times, in which case a match is reported when at least one of the captures match.
+
+Example: --qs '(call_expression function: (identifier) @f)' --capture f=foo --capture f=bar";
+
+#[rustfmt::skip]
+static DUMP_HELP_STR: &str = "\
+Report every token of the node kinds given with --kind, instead of searching PATTERN. Each token is reported as one match. The first positional argument is a path.
+
+Example: sg --rust --kind comment --dump src";
+
+#[rustfmt::skip]
+static EACH_CAPTURE_HELP_STR: &str = "\
+Report each capture of the query matches separately, with its own line and column, like PATTERN matches, instead of the lines of the matched nodes. Works with --vimgrep, --json, -l, -c etc. like PATTERN matches. Query matches without captures are not reported.
+
+Example: sg --rust --qs '(call_expression function: (identifier) @f)' --each-capture --vimgrep";
+
+#[rustfmt::skip]
+static QUERY_NAME_HELP_STR: &str = "\
+Search matches of the tree-sitter query in file NAME.scm in directory $XDG_CONFIG_HOME/sg/queries/<language>/ ($HOME/.config/sg/queries/<language>/ when XDG_CONFIG_HOME is not set), where <language> is the language flag without dashes: rust, ocaml, dart, js, ts, python, or go.
+
+When the directory doesn't have a query named NAME, the built-in query NAME is searched. Built-in queries for Rust: fn-defs, panic-calls, todo-comments, unsafe-blocks.";
+
+#[rustfmt::skip]
+static LIST_NODE_KINDS_HELP_STR: &str = "\
+Print the tree-sitter node kinds in the files, with the number of nodes of each kind, instead of searching. Anonymous nodes (keywords, punctuation) are quoted. Useful for finding the node kinds to use in queries, or the node kinds of the strings and comments of a language loaded with --parser.
+
+Example: sg --rust --list-node-kinds src/main.rs";
+
+#[rustfmt::skip]
+static LIST_QUERIES_HELP_STR: &str = "\
+Print the names and descriptions of the queries that can be searched with --qn, instead of searching. Lists the queries of the selected languages, or of all languages when no language is selected. Description of a query is the comment on the first line of the query.
+
+Example: sg --rust --list-queries";
+
+#[rustfmt::skip]
+static DEBUG_AST_HELP_STR: &str = "\
+Print the tree-sitter syntax trees of the files instead of searching. Each named node is printed on a separate line, with its field name in the parent node, kind, and start and end positions. Useful for writing queries.
+
+Example: sg --rust --debug-ast src/main.rs";
+
+#[rustfmt::skip]
+static PATH_HELP_STR: &str = "\
+Files or directories to search. Use '-' to search stdin. When no PATH is given sg searches the current directory, or stdin when stdin is not a terminal. Note that in some environments stdin is not a terminal even when no input is piped (e.g. editor integrations, 'ssh host sg ...'), in which case sg waits for input on stdin; pass '.' to search the current directory.";
+
+#[rustfmt::skip]
+static REPLACE_HELP_STR: &str = "\
+Replace matches with REPLACEMENT and write the files back. Implies --word, so only whole identifiers (or whole words in comments and strings) are replaced. Matches case sensitively unless one of -i, -S, -s is given. Use --dry-run to see the changes as a diff, or --preview to see the matched lines with the replacements, without writing the files.
+
+With a query (--qs, --qn, --query-file), the nodes matched by the query are replaced. @name in REPLACEMENT is replaced with the text of the capture 'name' (letters, digits, and '_'), and @@ with @. When matched nodes are nested, only the outermost nodes are replaced. With --each-capture the captures are replaced with REPLACEMENT instead, like PATTERN matches.
+
+Example: sg --rust --qs '(call_expression function: (identifier) @f arguments: (_) @args)' --replace 'log(@f, @args)' --dry-run";
+
+#[rustfmt::skip]
+static DEF_HELP_STR: &str = "\
+Only search names of function and method definitions (and let-bindings in OCaml), to find where a function is defined. Languages loaded with --parser are not supported.
+
+Example: sg --rust --def -w search_file";
+
+#[rustfmt::skip]
+static INVERT_MATCH_HELP_STR: &str = "\
+Print lines without matches. Only lines with at least one searched token (see --kind) are considered, other lines are never printed.";
 
 #[rustfmt::skip]
 static KIND_HELP_STR: &str = "\
-Comma-separated list of AST node kinds. When specified only search pattern in these kind of tree-sitter nodes. Possible values: 'identifier' (for identifiers and keywords), 'comment' (for comments), 'string' (for string literals). Default is 'identifier'.
+Comma-separated list of AST node kinds. When specified only search pattern in these kind of tree-sitter nodes. Possible values: 'identifier' (for identifiers and number literals), 'keyword' (for keywords, e.g. 'fn' and 'let' in Rust), 'comment' (for comments), 'doc_comment' (for doc comments, e.g. '///' and '//!' comments in Rust), 'string' (for string literals), 'number' (for number literals), 'char' (for character literals). Pattern is searched in the whole string literals including the quotes (so '\"test' matches strings starting with 'test'), and in character literals without the quotes. Columns, replacements, and matched texts in --json output are of the matched text only, which includes a quote only when the pattern does. Default is 'identifier,keyword'.
 
 Example: --kind identifier,comment,string";
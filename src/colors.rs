@@ -0,0 +1,152 @@
+//! Parsing color specs in `SG_COLORS`
+
+use ansi_term::{Colour, Style};
+
+/// Styles of the colored parts of the output
+#[derive(Debug, PartialEq)]
+pub(crate) struct Styles {
+    /// Style of file paths
+    pub(crate) file_path: Style,
+    /// Style of line numbers
+    pub(crate) line_num: Style,
+    /// Style of the matched parts of lines
+    pub(crate) match_: Style,
+    /// Style of the replaced text, in `--replace --preview`
+    pub(crate) removed: Style,
+    /// Style of the replacement text, in `--replace --preview`
+    pub(crate) added: Style,
+}
+
+impl Default for Styles {
+    fn default() -> Self {
+        Styles {
+            file_path: Colour::Green.bold(),
+            line_num: Colour::Yellow.bold(),
+            match_: Colour::Black.on(Colour::Yellow),
+            removed: Colour::Red.strikethrough(),
+            added: Colour::Green.normal(),
+        }
+    }
+}
+
+impl Styles {
+    /// Update styles with the comma-separated specs in `specs`. A spec is one of:
+    ///
+    /// - `{type}:fg:{color}`: set foreground color
+    /// - `{type}:bg:{color}`: set background color
+    /// - `{type}:style:{style}`: set or unset a style (`bold`, `nobold`, `underline`, ...)
+    /// - `{type}:none`: clear the style
+    ///
+    /// where `{type}` is `path`, `line`, `match`, `removed`, or `added`, and `{color}` is a color name (`black`, `red`,
+    /// `green`, `yellow`, `blue`, `magenta`, `cyan`, `white`) or a number between 0 and 255.
+    ///
+    /// Invalid specs are skipped. Returns an error message for each invalid spec.
+    pub(crate) fn update(&mut self, specs: &str) -> Vec<String> {
+        let mut errors = vec![];
+        for spec in specs.split(',').filter(|spec| !spec.is_empty()) {
+            if let Err(err) = self.update_one(spec) {
+                errors.push(format!("Invalid color spec '{}': {}", spec, err));
+            }
+        }
+        errors
+    }
+
+    fn update_one(&mut self, spec: &str) -> Result<(), String> {
+        let mut parts = spec.split(':');
+        let style = match parts.next().unwrap() {
+            "path" => &mut self.file_path,
+            "line" => &mut self.line_num,
+            "match" => &mut self.match_,
+            "removed" => &mut self.removed,
+            "added" => &mut self.added,
+            other => return Err(format!("unknown type '{}'", other)),
+        };
+
+        let attr = parts.next().ok_or("missing attribute")?;
+        if attr == "none" {
+            if parts.next().is_some() {
+                return Err("unexpected value after 'none'".to_owned());
+            }
+            *style = Style::new();
+            return Ok(());
+        }
+
+        let value = parts.next().ok_or("missing value")?;
+        if parts.next().is_some() {
+            return Err("too many parts".to_owned());
+        }
+
+        match attr {
+            "fg" => style.foreground = Some(parse_color(value)?),
+            "bg" => style.background = Some(parse_color(value)?),
+            "style" => match value {
+                "bold" => style.is_bold = true,
+                "nobold" => style.is_bold = false,
+                "dimmed" => style.is_dimmed = true,
+                "nodimmed" => style.is_dimmed = false,
+                "italic" => style.is_italic = true,
+                "noitalic" => style.is_italic = false,
+                "underline" => style.is_underline = true,
+                "nounderline" => style.is_underline = false,
+                "strikethrough" => style.is_strikethrough = true,
+                "nostrikethrough" => style.is_strikethrough = false,
+                other => return Err(format!("unknown style '{}'", other)),
+            },
+            other => return Err(format!("unknown attribute '{}'", other)),
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_color(color: &str) -> Result<Colour, String> {
+    Ok(match color {
+        "black" => Colour::Black,
+        "red" => Colour::Red,
+        "green" => Colour::Green,
+        "yellow" => Colour::Yellow,
+        "blue" => Colour::Blue,
+        "magenta" => Colour::Purple,
+        "cyan" => Colour::Cyan,
+        "white" => Colour::White,
+        other => match other.parse::<u8>() {
+            Ok(n) => Colour::Fixed(n),
+            Err(_) => return Err(format!("unknown color '{}'", other)),
+        },
+    })
+}
+
+#[test]
+fn test_update_styles() {
+    let mut styles = Styles::default();
+    let errors = styles.update("path:fg:blue,line:none,match:bg:208,match:style:underline");
+    assert!(errors.is_empty());
+    assert_eq!(
+        styles,
+        Styles {
+            file_path: Colour::Blue.bold(),
+            line_num: Style::new(),
+            match_: Colour::Black.on(Colour::Fixed(208)).underline(),
+            ..Styles::default()
+        }
+    );
+
+    // Invalid specs are skipped
+    let mut styles = Styles::default();
+    let errors = styles.update("path:fg:pink,line:style:nobold,foo:fg:red,match:bg");
+    assert_eq!(
+        errors,
+        vec![
+            "Invalid color spec 'path:fg:pink': unknown color 'pink'".to_owned(),
+            "Invalid color spec 'foo:fg:red': unknown type 'foo'".to_owned(),
+            "Invalid color spec 'match:bg': missing value".to_owned(),
+        ]
+    );
+    assert_eq!(
+        styles,
+        Styles {
+            line_num: Style::new().fg(Colour::Yellow),
+            ..Styles::default()
+        }
+    );
+}
@@ -0,0 +1,97 @@
+//! Printing syntax trees of files, to help with writing `--kind` filters and queries
+//! (`--list-node-kinds`, `--debug-ast`)
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use tree_sitter::Node;
+
+use crate::{report, Cfg};
+
+/// Print the node kinds in the syntax tree `root` of the file at `path`, with the number of
+/// nodes of each kind. Named nodes are printed first. Anonymous nodes (keywords, punctuation) are
+/// quoted, as in queries.
+pub(crate) fn report_node_kinds<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    root: Node,
+    first: &mut bool,
+) {
+    // Node kinds, sorted with named nodes first
+    let mut kinds: BTreeMap<(bool, &str), usize> = BTreeMap::new();
+
+    let mut cursor = root.walk();
+    'walk: loop {
+        let node = cursor.node();
+        *kinds.entry((!node.is_named(), node.kind())).or_default() += 1;
+
+        if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'walk;
+            }
+        }
+    }
+
+    *first = false;
+    report::report_path(stdout, cfg, path);
+    for ((anonymous, kind), count) in kinds {
+        if anonymous {
+            let _ = writeln!(stdout, "{:>7} {:?}", count, kind);
+        } else {
+            let _ = writeln!(stdout, "{:>7} {}", count, kind);
+        }
+    }
+}
+
+/// Print the syntax tree `root` of the file at `path`, one named node per line, indented by depth.
+/// Each node is printed with the field name in the parent node (if it has one), its kind, and its
+/// 1-based start and end positions as `line:column`.
+pub(crate) fn report_tree<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    root: Node,
+    first: &mut bool,
+) {
+    *first = false;
+    report::report_path(stdout, cfg, path);
+
+    let mut cursor = root.walk();
+    let mut depth = 0;
+    'walk: loop {
+        let node = cursor.node();
+        if node.is_named() {
+            let start = node.start_position();
+            let end = node.end_position();
+            let _ = write!(stdout, "{:indent$}", "", indent = depth * 2);
+            if let Some(field_name) = cursor.field_name() {
+                let _ = write!(stdout, "{}: ", field_name);
+            }
+            let _ = writeln!(
+                stdout,
+                "{} {}:{}-{}:{}",
+                node.kind(),
+                start.row + 1,
+                start.column + 1,
+                end.row + 1,
+                end.column + 1
+            );
+        }
+
+        if cursor.goto_first_child() {
+            depth += 1;
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'walk;
+            }
+            depth -= 1;
+        }
+    }
+}
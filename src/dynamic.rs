@@ -0,0 +1,454 @@
+//! Loading tree-sitter parsers from shared libraries
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use goblin::mach::Mach;
+use goblin::Object;
+use tree_sitter::Language;
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    /// Unable to read the shared library
+    IO(std::io::Error),
+    /// Unable to parse the shared library
+    Goblin(goblin::error::Error),
+    /// Unable to load the shared library or find the language symbol in it
+    Libloading(libloading::Error),
+    /// The library does not export a `tree_sitter_<name>` function
+    CantFindLangName,
+    /// The language was generated for a tree-sitter ABI version this version of tree-sitter can't
+    /// load
+    IncompatibleVersion(usize),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IO(err) => write!(f, "{}", err),
+            Error::Goblin(err) => write!(f, "unable to parse the library: {}", err),
+            Error::Libloading(err) => write!(f, "{}", err),
+            Error::CantFindLangName => write!(
+                f,
+                "could not find a {}* function in the library's symbols, \
+                pass the function name with --lang-sym",
+                LANG_SYM_PREFIX
+            ),
+            Error::IncompatibleVersion(version) => write!(
+                f,
+                "the parser was generated for tree-sitter ABI version {}, \
+                supported versions are {} to {}",
+                version,
+                tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION,
+                tree_sitter::LANGUAGE_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IO(err) => Some(err),
+            Error::Goblin(err) => Some(err),
+            Error::Libloading(err) => Some(err),
+            Error::CantFindLangName | Error::IncompatibleVersion(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::IO(err)
+    }
+}
+
+impl From<goblin::error::Error> for Error {
+    fn from(err: goblin::error::Error) -> Self {
+        Error::Goblin(err)
+    }
+}
+
+impl From<libloading::Error> for Error {
+    fn from(err: libloading::Error) -> Self {
+        Error::Libloading(err)
+    }
+}
+
+/// Prefix of the functions that return tree-sitter languages
+const LANG_SYM_PREFIX: &str = "tree_sitter_";
+
+/// Suffixes of the external scanner functions generated by tree-sitter. These functions have the
+/// `tree_sitter_<name>` prefix too, but they don't return languages.
+const SCANNER_SYM_SUFFIXES: [&str; 5] = [
+    "_external_scanner_create",
+    "_external_scanner_destroy",
+    "_external_scanner_scan",
+    "_external_scanner_serialize",
+    "_external_scanner_deserialize",
+];
+
+/// Name of the file caching the language functions found in libraries, in `$XDG_CACHE_HOME/sg`
+const LANG_SYM_CACHE_FILE: &str = "lang-syms";
+
+/// Extensions of the shared libraries loaded from `--parser-dir` directories
+const LIB_EXTENSIONS: [&str; 3] = ["so", "dylib", "dll"];
+
+/// Name of the file mapping language names to file extensions in `--parser-dir` directories
+pub(crate) const EXTENSIONS_FILE: &str = "sg-extensions";
+
+/// Shared libraries in a `--parser-dir` directory, with the file extensions of the languages in
+/// the directory's `sg-extensions` file
+#[derive(Debug)]
+pub(crate) struct ParserDir {
+    /// Paths of the shared libraries, sorted
+    pub(crate) libs: Vec<PathBuf>,
+    /// Language names mapped to extensions of the files to search with the language. Languages
+    /// not in the map are searched in files with the language name as the extension, as with
+    /// `--parser` without `--ext`.
+    pub(crate) exts: HashMap<String, Vec<String>>,
+}
+
+/// Find the shared libraries and read the `sg-extensions` file in the directory `dir`
+pub(crate) fn read_parser_dir(dir: &Path) -> Result<ParserDir, Error> {
+    let mut libs = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_lib = path
+            .extension()
+            .is_some_and(|ext| LIB_EXTENSIONS.iter().any(|lib_ext| ext == *lib_ext));
+        if is_lib && path.is_file() {
+            libs.push(path);
+        }
+    }
+    libs.sort();
+
+    let exts = match std::fs::read_to_string(dir.join(EXTENSIONS_FILE)) {
+        Ok(contents) => parse_extensions(&contents),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(ParserDir { libs, exts })
+}
+
+/// Parse a `sg-extensions` file. Each line is a language name followed by the extensions of the
+/// files to search with the language, separated by whitespace. Empty lines and lines starting with
+/// `#` are skipped.
+fn parse_extensions(contents: &str) -> HashMap<String, Vec<String>> {
+    let mut exts = HashMap::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let lang_name = words.next().unwrap().to_owned();
+        let lang_exts = words
+            .map(|ext| ext.trim_start_matches('.').to_owned())
+            .collect();
+        exts.insert(lang_name, lang_exts);
+    }
+    exts
+}
+
+/// Load the tree-sitter language in the shared library at `path`. `lang_sym` is the name of the
+/// function that returns the language (`tree_sitter_<name>`). When not given, the function is
+/// found in the symbols exported by the library. Supports ELF, Mach-O, and PE libraries.
+///
+/// Returns the language name (`lang_sym` without the `tree_sitter_` prefix) and the language.
+pub(crate) fn load_parser(
+    path: &Path,
+    lang_sym: Option<&str>,
+) -> Result<(String, Language), Error> {
+    let lang_sym = match lang_sym {
+        Some(lang_sym) => lang_sym.to_owned(),
+        None => find_lang_sym_cached(path)?,
+    };
+
+    unsafe {
+        let lib = libloading::Library::new(path)?;
+        let lang_fn: libloading::Symbol<unsafe extern "C" fn() -> Language> =
+            lib.get(lang_sym.as_bytes())?;
+        let language = lang_fn();
+        check_language_version(language.version())?;
+        // The language refers to static data in the library, keep the library loaded until the
+        // process exits
+        std::mem::forget(lib);
+
+        let lang_name = lang_sym
+            .strip_prefix(LANG_SYM_PREFIX)
+            .unwrap_or(&lang_sym)
+            .to_owned();
+        Ok((lang_name, language))
+    }
+}
+
+/// Check that a language with ABI `version` can be used with the linked tree-sitter. Setting a
+/// parser's language to an incompatible language fails.
+fn check_language_version(version: usize) -> Result<(), Error> {
+    if (tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION..=tree_sitter::LANGUAGE_VERSION)
+        .contains(&version)
+    {
+        Ok(())
+    } else {
+        Err(Error::IncompatibleVersion(version))
+    }
+}
+
+/// Find the name of the language function in the library at `path`. Finding the function requires
+/// reading and parsing the whole library, so found functions are cached in `lang_sym_cache_path`,
+/// keyed by the library's absolute path and modification time.
+fn find_lang_sym_cached(path: &Path) -> Result<String, Error> {
+    let key = lang_sym_cache_key(path);
+    let cache_path = lang_sym_cache_path();
+    let cache = cache_path
+        .as_ref()
+        .and_then(|cache_path| std::fs::read_to_string(cache_path).ok())
+        .unwrap_or_default();
+
+    if let Some((lib_path, modified)) = &key {
+        if let Some(lang_sym) = cached_lang_sym(&cache, lib_path, *modified) {
+            return Ok(lang_sym.to_owned());
+        }
+    }
+
+    let bytes = std::fs::read(path)?;
+    let lang_sym = find_lang_sym(&bytes)?.ok_or(Error::CantFindLangName)?;
+
+    // Failing to update the cache is not an error, the function is found again next time
+    if let (Some(cache_path), Some((lib_path, modified))) = (cache_path, key) {
+        let new_cache = update_lang_sym_cache(&cache, &lib_path, modified, &lang_sym);
+        let _ = write_lang_sym_cache(&cache_path, &new_cache);
+    }
+
+    Ok(lang_sym)
+}
+
+/// Path of the language function cache: `$XDG_CACHE_HOME/sg/lang-syms`, or
+/// `$HOME/.cache/sg/lang-syms` when `XDG_CACHE_HOME` is not set. `None` when neither is set.
+fn lang_sym_cache_path() -> Option<PathBuf> {
+    let cache_dir = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache_dir.join("sg").join(LANG_SYM_CACHE_FILE))
+}
+
+/// Absolute path and modification time (in nanoseconds since Unix epoch) of the library at `path`.
+/// `None` when these can't be found, or when the path can't be stored in the cache.
+fn lang_sym_cache_key(path: &Path) -> Option<(String, u128)> {
+    let lib_path = std::fs::canonicalize(path)
+        .ok()?
+        .into_os_string()
+        .into_string()
+        .ok()?;
+    if lib_path.contains('\n') {
+        return None;
+    }
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    Some((lib_path, modified))
+}
+
+/// Find the language function of the library at `lib_path` modified at `modified` in the cache
+/// file contents `cache`. Each line of the file is a library's modification time, language
+/// function, and path, separated by tabs.
+fn cached_lang_sym<'a>(cache: &'a str, lib_path: &str, modified: u128) -> Option<&'a str> {
+    cache.lines().find_map(|line| {
+        let mut fields = line.splitn(3, '\t');
+        let entry_modified = fields.next()?;
+        let lang_sym = fields.next()?;
+        let entry_path = fields.next()?;
+        (entry_path == lib_path && entry_modified == modified.to_string()).then_some(lang_sym)
+    })
+}
+
+/// Add the language function of the library at `lib_path` to the cache file contents `cache`,
+/// replacing the entry of an older version of the library. Returns the new contents.
+fn update_lang_sym_cache(cache: &str, lib_path: &str, modified: u128, lang_sym: &str) -> String {
+    let mut new_cache = String::with_capacity(cache.len());
+    for line in cache.lines() {
+        if line.splitn(3, '\t').nth(2) != Some(lib_path) {
+            new_cache.push_str(line);
+            new_cache.push('\n');
+        }
+    }
+    new_cache.push_str(&format!("{}\t{}\t{}\n", modified, lang_sym, lib_path));
+    new_cache
+}
+
+/// Write the cache file atomically, so that other sg processes don't read a partially written
+/// file
+fn write_lang_sym_cache(cache_path: &Path, contents: &str) -> std::io::Result<()> {
+    if let Some(dir) = cache_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp_path = cache_path.with_extension(std::process::id().to_string());
+    std::fs::write(&tmp_path, contents)?;
+    std::fs::rename(&tmp_path, cache_path)
+}
+
+/// Find the name of the language function in the symbols exported by the shared library
+/// `bytes`
+fn find_lang_sym(bytes: &[u8]) -> Result<Option<String>, Error> {
+    match Object::parse(bytes)? {
+        Object::Elf(elf) => Ok(find_lang_sym_in(
+            elf.dynsyms
+                .iter()
+                .filter(|sym| sym.is_function() && !sym.is_import())
+                .filter_map(|sym| elf.dynstrtab.get_at(sym.st_name)),
+        )),
+
+        Object::Mach(Mach::Binary(macho)) => find_lang_sym_macho(&macho),
+
+        Object::Mach(Mach::Fat(multi_arch)) => {
+            for arch_idx in 0..multi_arch.narches {
+                let macho = multi_arch.get(arch_idx)?;
+                if let Some(lang_sym) = find_lang_sym_macho(&macho)? {
+                    return Ok(Some(lang_sym));
+                }
+            }
+            Ok(None)
+        }
+
+        Object::PE(pe) => Ok(find_lang_sym_in(
+            pe.exports.iter().filter_map(|export| export.name),
+        )),
+
+        _ => Ok(None),
+    }
+}
+
+fn find_lang_sym_macho(macho: &goblin::mach::MachO) -> Result<Option<String>, Error> {
+    let exports = macho.exports()?;
+    // Mach-O symbols of C functions are prefixed with '_'
+    Ok(find_lang_sym_in(exports.iter().map(|export| {
+        export
+            .name
+            .strip_prefix('_')
+            .unwrap_or(export.name.as_str())
+    })))
+}
+
+/// Find the language function in symbol names `syms`
+fn find_lang_sym_in<'a, I: Iterator<Item = &'a str>>(mut syms: I) -> Option<String> {
+    syms.find(|sym| {
+        sym.len() > LANG_SYM_PREFIX.len()
+            && sym.starts_with(LANG_SYM_PREFIX)
+            && !SCANNER_SYM_SUFFIXES
+                .iter()
+                .any(|suffix| sym.ends_with(suffix))
+    })
+    .map(|sym| sym.to_owned())
+}
+
+#[test]
+fn test_find_lang_sym_in() {
+    assert_eq!(
+        find_lang_sym_in(
+            [
+                "tree_sitter_rust_external_scanner_create",
+                "tree_sitter_rust_external_scanner_scan",
+                "malloc",
+                "tree_sitter_rust",
+            ]
+            .into_iter()
+        ),
+        Some("tree_sitter_rust".to_owned())
+    );
+    assert_eq!(
+        find_lang_sym_in(
+            [
+                "tree_sitter_",
+                "tree_sitter_rust_external_scanner_destroy",
+                "tree_sitter_rust_external_scanner_serialize",
+                "tree_sitter_rust_external_scanner_deserialize",
+            ]
+            .into_iter()
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_lang_sym_cache() {
+    let cache = update_lang_sym_cache("", "/lib/libtree-sitter-c.so", 1, "tree_sitter_c");
+    let cache = update_lang_sym_cache(&cache, "/lib/libtree-sitter-go.so", 2, "tree_sitter_go");
+    assert_eq!(
+        cached_lang_sym(&cache, "/lib/libtree-sitter-c.so", 1),
+        Some("tree_sitter_c")
+    );
+    assert_eq!(
+        cached_lang_sym(&cache, "/lib/libtree-sitter-go.so", 2),
+        Some("tree_sitter_go")
+    );
+
+    // Modified libraries are not found
+    assert_eq!(cached_lang_sym(&cache, "/lib/libtree-sitter-c.so", 3), None);
+
+    // New versions of libraries replace the old ones
+    let cache = update_lang_sym_cache(&cache, "/lib/libtree-sitter-c.so", 3, "tree_sitter_cc");
+    assert_eq!(cache.lines().count(), 2);
+    assert_eq!(cached_lang_sym(&cache, "/lib/libtree-sitter-c.so", 1), None);
+    assert_eq!(
+        cached_lang_sym(&cache, "/lib/libtree-sitter-c.so", 3),
+        Some("tree_sitter_cc")
+    );
+}
+
+#[test]
+fn test_read_parser_dir() {
+    let parser_dir = read_parser_dir(Path::new("test_files/dynamic")).unwrap();
+    assert_eq!(
+        parser_dir.libs,
+        vec![PathBuf::from("test_files/dynamic/not_a_library.so")]
+    );
+    assert_eq!(parser_dir.exts.len(), 2);
+    assert_eq!(parser_dir.exts["c"], vec!["c", "h"]);
+    assert_eq!(parser_dir.exts["cpp"], vec!["cpp", "hpp", "cc"]);
+
+    assert!(matches!(
+        read_parser_dir(Path::new("test_files/dynamic/missing")),
+        Err(Error::IO(_))
+    ));
+}
+
+#[test]
+fn test_load_parser_errors() {
+    let err = load_parser(Path::new("test_files/dynamic/missing.so"), None)
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::IO(_)));
+
+    let err = load_parser(Path::new("test_files/dynamic/not_a_library.so"), None)
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::CantFindLangName));
+    assert!(err
+        .to_string()
+        .ends_with("pass the function name with --lang-sym"));
+}
+
+#[test]
+fn test_check_language_version() {
+    assert!(check_language_version(tree_sitter::LANGUAGE_VERSION).is_ok());
+    assert!(check_language_version(tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION).is_ok());
+
+    let err = check_language_version(tree_sitter::LANGUAGE_VERSION + 1)
+        .err()
+        .unwrap();
+    assert!(matches!(err, Error::IncompatibleVersion(_)));
+    assert!(err.to_string().starts_with(&format!(
+        "the parser was generated for tree-sitter ABI version {}",
+        tree_sitter::LANGUAGE_VERSION + 1
+    )));
+    assert!(matches!(
+        check_language_version(tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION - 1),
+        Err(Error::IncompatibleVersion(_))
+    ));
+}
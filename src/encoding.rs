@@ -0,0 +1,165 @@
+//! Decoding and encoding files with byte order marks (UTF-8 with a BOM, UTF-16), and files in the
+//! encoding given with `--encoding`
+
+use std::io;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Encoding of a file, detected by the byte order mark at the beginning of the file. Files
+/// without a BOM are in the encoding given with `--encoding`, or UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// Encoding given with `--encoding`, without a BOM
+    Other(&'static encoding_rs::Encoding),
+}
+
+impl Encoding {
+    /// Detect encoding of a file from its first bytes. `default` is the encoding of files without
+    /// a BOM, UTF-8 when not given.
+    pub(crate) fn detect(
+        bytes: &[u8],
+        default: Option<&'static encoding_rs::Encoding>,
+    ) -> Encoding {
+        if bytes.starts_with(UTF8_BOM) {
+            Encoding::Utf8Bom
+        } else if bytes.starts_with(UTF16LE_BOM) {
+            Encoding::Utf16Le
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            Encoding::Utf16Be
+        } else {
+            default.map_or(Encoding::Utf8, Encoding::Other)
+        }
+    }
+
+    /// Whether the encoding is UTF-16. UTF-16 files have NUL bytes in ASCII characters, these
+    /// should not be considered binary.
+    pub(crate) fn is_utf16(self) -> bool {
+        match self {
+            Encoding::Utf16Le | Encoding::Utf16Be => true,
+            Encoding::Other(encoding) => {
+                encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE
+            }
+            Encoding::Utf8 | Encoding::Utf8Bom => false,
+        }
+    }
+
+    /// Decode `bytes` of a file in this encoding to UTF-8, without the BOM
+    pub(crate) fn decode(self, mut bytes: Vec<u8>) -> io::Result<String> {
+        let to_u16: fn([u8; 2]) -> u16 = match self {
+            Encoding::Utf8 => return utf8(bytes),
+            Encoding::Utf8Bom => {
+                bytes.drain(..UTF8_BOM.len());
+                return utf8(bytes);
+            }
+            Encoding::Utf16Le => u16::from_le_bytes,
+            Encoding::Utf16Be => u16::from_be_bytes,
+            Encoding::Other(encoding) => {
+                return encoding
+                    .decode_without_bom_handling_and_without_replacement(&bytes)
+                    .map(|text| text.into_owned())
+                    .ok_or_else(|| invalid_data(format!("invalid {} data", encoding.name())));
+            }
+        };
+
+        // UTF-16 BOMs are 2 bytes
+        let units = bytes[2..].chunks_exact(2);
+        if !units.remainder().is_empty() {
+            return Err(invalid_data("UTF-16 file with odd number of bytes"));
+        }
+        char::decode_utf16(units.map(|unit| to_u16([unit[0], unit[1]])))
+            .collect::<Result<String, _>>()
+            .map_err(invalid_data)
+    }
+
+    /// Encode `text` in this encoding, with the BOM. Fails when `text` has characters that can't
+    /// be represented in the encoding.
+    pub(crate) fn encode(self, text: &str) -> io::Result<Vec<u8>> {
+        let (to_bytes, bom): (fn(u16) -> [u8; 2], bool) = match self {
+            Encoding::Utf8 => return Ok(text.as_bytes().to_vec()),
+            Encoding::Utf8Bom => return Ok([UTF8_BOM, text.as_bytes()].concat()),
+            Encoding::Utf16Le => (u16::to_le_bytes, true),
+            Encoding::Utf16Be => (u16::to_be_bytes, true),
+            // `encoding_rs` only decodes UTF-16, it encodes UTF-16 as UTF-8
+            Encoding::Other(encoding) if encoding == encoding_rs::UTF_16LE => {
+                (u16::to_le_bytes, false)
+            }
+            Encoding::Other(encoding) if encoding == encoding_rs::UTF_16BE => {
+                (u16::to_be_bytes, false)
+            }
+            Encoding::Other(encoding) => {
+                let (bytes, _, unmappable) = encoding.encode(text);
+                if unmappable {
+                    return Err(invalid_data(format!(
+                        "can't encode new contents in {}",
+                        encoding.name()
+                    )));
+                }
+                return Ok(bytes.into_owned());
+            }
+        };
+
+        let mut bytes = Vec::with_capacity(2 + text.len() * 2);
+        if bom {
+            bytes.extend(to_bytes(0xFEFF));
+        }
+        for unit in text.encode_utf16() {
+            bytes.extend(to_bytes(unit));
+        }
+        Ok(bytes)
+    }
+}
+
+fn utf8(bytes: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(bytes).map_err(invalid_data)
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[test]
+fn test_encoding() {
+    let text = "fn ok() { \"ü😀\" }\n";
+    for encoding in [
+        Encoding::Utf8,
+        Encoding::Utf8Bom,
+        Encoding::Utf16Le,
+        Encoding::Utf16Be,
+    ] {
+        let bytes = encoding.encode(text).unwrap();
+        assert_eq!(Encoding::detect(&bytes, None), encoding);
+        assert_eq!(encoding.decode(bytes).unwrap(), text);
+    }
+
+    // Unpaired surrogate
+    assert!(Encoding::Utf16Le
+        .decode(vec![0xFF, 0xFE, 0x00, 0xD8])
+        .is_err());
+    // Odd number of bytes
+    assert!(Encoding::Utf16Be.decode(vec![0xFE, 0xFF, 0x00]).is_err());
+
+    // `--encoding` is used for files without a BOM
+    let latin1 = encoding_rs::Encoding::for_label(b"latin1");
+    assert_eq!(
+        Encoding::detect(b"\xEF\xBB\xBFfn", latin1),
+        Encoding::Utf8Bom
+    );
+    let encoding = Encoding::detect(b"caf\xE9", latin1);
+    assert_eq!(encoding, Encoding::Other(encoding_rs::WINDOWS_1252));
+    assert_eq!(encoding.decode(b"caf\xE9".to_vec()).unwrap(), "café");
+    assert_eq!(encoding.encode("café").unwrap(), b"caf\xE9");
+    assert!(encoding.encode("😀").is_err());
+
+    let encoding = Encoding::Other(encoding_rs::UTF_16LE);
+    assert_eq!(encoding.decode(b"f\0n\0".to_vec()).unwrap(), "fn");
+    assert_eq!(encoding.encode("fn").unwrap(), b"f\0n\0");
+}
@@ -1,15 +1,33 @@
 #![allow(clippy::too_many_arguments)]
 
-use std::borrow::Cow;
-use std::cell::RefCell;
-use std::ffi::OsString;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::io::{IsTerminal, Write};
-use std::path::Path;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 
-use tree_sitter::{Language, Node, Parser};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use rayon::prelude::*;
+use tree_sitter::{Language, Node, Parser, Tree};
 
+use progress::Progress;
+
+mod cache;
 mod cli;
+mod colors;
+mod debug;
+mod dynamic;
+mod encoding;
+mod progress;
+mod query;
+mod replace;
+mod report;
+mod search;
+mod watch;
 
 #[cfg(test)]
 mod tests;
@@ -17,8 +35,237 @@ mod tests;
 extern "C" {
     fn tree_sitter_dart() -> Language;
     fn tree_sitter_ocaml() -> Language;
+    fn tree_sitter_ocaml_interface() -> Language;
     fn tree_sitter_rust() -> Language;
     fn tree_sitter_javascript() -> Language;
+    fn tree_sitter_typescript() -> Language;
+    fn tree_sitter_tsx() -> Language;
+    fn tree_sitter_python() -> Language;
+    fn tree_sitter_go() -> Language;
+}
+
+/// tree-sitter node kinds of a language for the syntactic categories that can be selected with
+/// `--kind`. Leaf nodes that are not comments or string or character literals are considered
+/// identifiers.
+struct LangNodeKinds {
+    /// Comment node kinds
+    comment_kinds: &'static [&'static str],
+    /// Whether a comment with the node kind and text is a doc comment, for `--kind doc_comment`
+    is_doc_comment: fn(&str, &str) -> bool,
+    /// String literal node kinds
+    string_kinds: &'static [&'static str],
+    /// Number literal node kinds
+    number_kinds: &'static [&'static str],
+    /// Character literal node kinds
+    char_kinds: &'static [&'static str],
+    /// Identifier node kinds, including names of types and fields. When empty all named leaf nodes
+    /// are identifiers.
+    identifier_kinds: &'static [&'static str],
+    /// Named node kinds of keywords (e.g. `self` in Rust). Anonymous nodes of words (e.g. `fn`,
+    /// `let`) are always keywords.
+    keyword_kinds: &'static [&'static str],
+    /// Whether leaf nodes in comments and literals are also searched as identifiers. Identifier
+    /// search in OCaml and Dart has always included comments and string contents (see issue #5),
+    /// this keeps it that way.
+    literal_tokens_are_ids: bool,
+    /// Function definition node kinds, with the fields of the defined names. With `--def` only
+    /// identifiers in these fields are searched.
+    def_name_fields: &'static [(&'static str, &'static str)],
+    /// Definition node kinds that contain other code (functions, classes, modules etc.), with the
+    /// fields of the defined names. With `--context-symbol` the name of the innermost definition
+    /// is reported with each match.
+    symbol_name_fields: &'static [(&'static str, &'static str)],
+}
+
+static RUST_NODE_KINDS: LangNodeKinds = LangNodeKinds {
+    comment_kinds: &["line_comment", "block_comment"],
+    is_doc_comment: is_rust_doc_comment,
+    // Byte strings (`b"..."`) are `string_literal`s, raw byte strings (`br"..."`) are
+    // `raw_string_literal`s
+    string_kinds: &["string_literal", "raw_string_literal"],
+    number_kinds: &["integer_literal", "float_literal"],
+    char_kinds: &["char_literal"],
+    identifier_kinds: &[
+        "identifier",
+        "type_identifier",
+        "field_identifier",
+        "shorthand_field_identifier",
+        "primitive_type",
+        "metavariable",
+    ],
+    keyword_kinds: &["self", "super", "crate", "mutable_specifier"],
+    literal_tokens_are_ids: false,
+    def_name_fields: &[
+        ("function_item", "name"),
+        ("function_signature_item", "name"),
+    ],
+    symbol_name_fields: &[
+        ("function_item", "name"),
+        ("impl_item", "type"),
+        ("trait_item", "name"),
+        ("mod_item", "name"),
+    ],
+};
+
+static OCAML_NODE_KINDS: LangNodeKinds = LangNodeKinds {
+    comment_kinds: &["comment"],
+    is_doc_comment: is_ocaml_doc_comment,
+    string_kinds: &["string", "quoted_string"],
+    number_kinds: &["number"],
+    char_kinds: &["character"],
+    identifier_kinds: &[],
+    keyword_kinds: &[],
+    literal_tokens_are_ids: true,
+    def_name_fields: &[("let_binding", "pattern")],
+    symbol_name_fields: &[("let_binding", "pattern")],
+};
+
+static DART_NODE_KINDS: LangNodeKinds = LangNodeKinds {
+    comment_kinds: &["comment", "documentation_comment"],
+    is_doc_comment: is_dart_doc_comment,
+    string_kinds: &["string_literal"],
+    number_kinds: &[
+        "decimal_integer_literal",
+        "hex_integer_literal",
+        "decimal_floating_point_literal",
+    ],
+    char_kinds: &[],
+    identifier_kinds: &[],
+    keyword_kinds: &[],
+    literal_tokens_are_ids: true,
+    def_name_fields: &[("function_signature", "name")],
+    // Function signatures don't contain the function bodies
+    symbol_name_fields: &[],
+};
+
+// Also used for TypeScript
+static JS_NODE_KINDS: LangNodeKinds = LangNodeKinds {
+    comment_kinds: &["comment"],
+    is_doc_comment: is_js_doc_comment,
+    string_kinds: &["string", "template_string"],
+    number_kinds: &["number"],
+    char_kinds: &[],
+    identifier_kinds: &[
+        "identifier",
+        "property_identifier",
+        "private_property_identifier",
+        "shorthand_property_identifier",
+        "shorthand_property_identifier_pattern",
+        "statement_identifier",
+        "type_identifier",
+    ],
+    keyword_kinds: &[
+        "this",
+        "this_type",
+        "super",
+        "true",
+        "false",
+        "null",
+        "undefined",
+    ],
+    literal_tokens_are_ids: false,
+    def_name_fields: &[
+        ("function_declaration", "name"),
+        ("generator_function_declaration", "name"),
+        ("method_definition", "name"),
+        ("function_signature", "name"),
+        ("method_signature", "name"),
+        ("abstract_method_signature", "name"),
+    ],
+    symbol_name_fields: &[
+        ("function_declaration", "name"),
+        ("generator_function_declaration", "name"),
+        ("method_definition", "name"),
+        ("class_declaration", "name"),
+        ("abstract_class_declaration", "name"),
+        ("interface_declaration", "name"),
+    ],
+};
+
+static PYTHON_NODE_KINDS: LangNodeKinds = LangNodeKinds {
+    comment_kinds: &["comment"],
+    // Docstrings are strings
+    is_doc_comment: no_doc_comments,
+    string_kinds: &["string"],
+    number_kinds: &["integer", "float"],
+    char_kinds: &[],
+    identifier_kinds: &["identifier"],
+    keyword_kinds: &["true", "false", "none"],
+    literal_tokens_are_ids: false,
+    def_name_fields: &[("function_definition", "name")],
+    symbol_name_fields: &[
+        ("function_definition", "name"),
+        ("class_definition", "name"),
+    ],
+};
+
+static GO_NODE_KINDS: LangNodeKinds = LangNodeKinds {
+    comment_kinds: &["comment"],
+    is_doc_comment: no_doc_comments,
+    string_kinds: &["interpreted_string_literal", "raw_string_literal"],
+    number_kinds: &["int_literal", "float_literal", "imaginary_literal"],
+    char_kinds: &["rune_literal"],
+    identifier_kinds: &[
+        "identifier",
+        "field_identifier",
+        "type_identifier",
+        "package_identifier",
+        "label_name",
+        "blank_identifier",
+    ],
+    keyword_kinds: &["true", "false", "nil", "iota"],
+    literal_tokens_are_ids: false,
+    def_name_fields: &[
+        ("function_declaration", "name"),
+        ("method_declaration", "name"),
+        ("method_spec", "name"),
+    ],
+    symbol_name_fields: &[
+        ("function_declaration", "name"),
+        ("method_declaration", "name"),
+    ],
+};
+
+/// Node kinds of languages loaded with `--parser` are not known, all named leaf nodes other than
+/// keywords are identifiers
+static DYNAMIC_NODE_KINDS: LangNodeKinds = LangNodeKinds {
+    comment_kinds: &[],
+    is_doc_comment: no_doc_comments,
+    string_kinds: &[],
+    number_kinds: &[],
+    char_kinds: &[],
+    identifier_kinds: &[],
+    keyword_kinds: &[],
+    literal_tokens_are_ids: false,
+    def_name_fields: &[],
+    symbol_name_fields: &[],
+};
+
+/// `///` and `//!` line comments, `/** */` and `/*! */` block comments. `////` and `/*** */` are
+/// regular comments.
+fn is_rust_doc_comment(_kind: &str, text: &str) -> bool {
+    (text.starts_with("///") && !text.starts_with("////"))
+        || text.starts_with("//!")
+        || (text.starts_with("/**") && !text.starts_with("/***") && text != "/**/")
+        || text.starts_with("/*!")
+}
+
+/// `(** *)` comments. `(*** *)` are regular comments.
+fn is_ocaml_doc_comment(_kind: &str, text: &str) -> bool {
+    text.starts_with("(**") && !text.starts_with("(***") && text != "(**)"
+}
+
+fn is_dart_doc_comment(kind: &str, _text: &str) -> bool {
+    kind == "documentation_comment"
+}
+
+/// JSDoc `/** */` comments
+fn is_js_doc_comment(_kind: &str, text: &str) -> bool {
+    text.starts_with("/**") && text != "/**/"
+}
+
+fn no_doc_comments(_kind: &str, _text: &str) -> bool {
+    false
 }
 
 struct Cfg {
@@ -26,139 +273,900 @@ struct Cfg {
     color: bool,
     // Print column number
     column: bool,
-    // Group matches by file
+    // Print byte offset of the first match in matched lines
+    byte_offset: bool,
+    // Print name of the definition containing the first match in matched lines
+    context_symbol: bool,
+    // Number of columns to count for a tab character in column numbers
+    tab_width: usize,
+    // Separate matches of different files with an empty line
     group: bool,
-    // Pattern to search
-    pattern: String,
+    // Print file name once before matches of the file, instead of in each match
+    heading: bool,
+    // Print line numbers of matched and context lines. Separators after line numbers are also
+    // omitted when not printing line numbers.
+    line_number: bool,
+    // Print matches as JSON objects, one per line
+    json: bool,
+    // With `json`, print each JSON object on multiple lines
+    json_pretty: bool,
+    // Print each match on a separate line as `path:line:column:line`
+    vimgrep: bool,
+    // Print a NUL byte after file paths, instead of the character that normally follows them
+    null: bool,
+    // Replace matches with this string and write the files back
+    replace: Option<String>,
+    // With `replace`, print a diff of the changes instead of writing the files
+    dry_run: bool,
+    // With `replace`, print matched lines with the matches and replacements instead of writing
+    // the files
+    preview: bool,
+    // Print lines without matches, instead of lines with matches
+    invert_match: bool,
+    // Only search names of function definitions
+    def: bool,
+    // When searching a query, print all lines of the matched nodes
+    full_node: bool,
+    // When searching a query, report each capture separately, like pattern matches
+    each_capture: bool,
+    // Print node kinds in the files instead of searching
+    list_node_kinds: bool,
+    // Print syntax trees of the files instead of searching
+    debug_ast: bool,
+    // Only print paths of files with matches
+    files_with_matches: bool,
+    // Only print paths of files without matches
+    files_without_matches: bool,
+    // Only print number of matches in each file
+    count: bool,
+    // With `count`, also print files without matches
+    count_zero: bool,
+    // Only print number of matches in each matched line
+    count_per_line: bool,
+    // Maximum number of matches to report in a file
+    max_count: Option<usize>,
+    // Maximum number of matches to report in all files
+    max_total_matches: Option<usize>,
+    // Number of lines to print before matched lines
+    before_context: usize,
+    // Number of lines to print after matched lines
+    after_context: usize,
+    // Print lines of the innermost node of one of these kinds containing a match as context
+    context_node_kinds: Vec<String>,
+    // Patterns to search
+    patterns: search::Patterns,
     // tree-sitter node kind, when available search pattern in this kind of nodes
     node_kinds: cli::NodeKinds,
-    // Match case sensitively?
-    case_sensitive: bool,
     // Only match whole words?
     whole_word: bool,
-    // tree-sitter parser
-    parser: RefCell<Parser>,
-    // Extension of files to search
-    ext: &'static str,
+    // Also search lines for matches spanning multiple tokens
+    text_fallback: bool,
+    // Match characters of the patterns in order, not necessarily contiguously
+    fuzzy: bool,
+    // Extensions of files to search, with the languages to search them with. Each search thread
+    // has its own parser.
+    exts: Vec<ExtLang>,
+    // Index of the language in `exts` to search stdin and files with other extensions given as
+    // `PATH`. `None` with `--all`, in which case these files are skipped.
+    default_lang: Option<usize>,
+    // Files to search when walking directories. When empty all files with the searched extensions
+    // are searched.
+    include: GlobSet,
+    // Files to skip when walking directories
+    exclude: GlobSet,
+    // Directories to skip when walking directories
+    exclude_dir: GlobSet,
+    // Follow symlinks to directories when walking directories
+    follow: bool,
+    // Walk hidden files and directories
+    hidden: bool,
+    // Order of the files and directories walked, `None` means file system order
+    sort: Option<cli::Sort>,
+    // Search binary files
+    text: bool,
+    // Encoding of the files without a byte order mark, UTF-8 when not given
+    encoding: Option<&'static encoding_rs::Encoding>,
+    // With `--cache`, contents and syntax trees of recently searched files
+    tree_cache: Option<cache::TreeCache>,
+    // Maximum depth of directories to walk, 0 means only the files in the searched directories
+    max_depth: Option<usize>,
     // Style to use for file paths
     file_path_style: ansi_term::Style,
     // Style to use for line numbres
     line_num_style: ansi_term::Style,
     // Style to use for highlighting matched parts
     match_style: ansi_term::Style,
+    // Style to use for replaced parts, with `preview`
+    removed_style: ansi_term::Style,
+    // Style to use for replacements, with `preview`
+    added_style: ansi_term::Style,
+}
+
+/// A file extension to search, with the language to search the files with
+struct ExtLang {
+    ext: String,
+    // tree-sitter language to parse the files with
+    language: Language,
+    // Node kinds of the language
+    node_kinds: &'static LangNodeKinds,
+    // Query to search with `--qs` and `--qn`, compiled for `language`
+    query: Option<query::Query>,
+}
+
+impl ExtLang {
+    /// Whether file extension `ext` is this extension. Extensions are compared case
+    /// insensitively, e.g. `FOO.RS` is a Rust file.
+    fn matches(&self, ext: &OsStr) -> bool {
+        ext.eq_ignore_ascii_case(&self.ext)
+    }
+}
+
+impl Cfg {
+    /// Language to search the file at `path` with
+    fn language(&self, path: &Path) -> Option<&ExtLang> {
+        path.extension()
+            .and_then(|ext| self.exts.iter().find(|ext_lang| ext_lang.matches(ext)))
+            .or_else(|| self.default_lang.map(|idx| &self.exts[idx]))
+    }
+}
+
+/// Counters for `--stats`. Updated by the search threads.
+#[derive(Debug, Default)]
+struct Stats {
+    /// Number of directories walked
+    dirs: AtomicUsize,
+    /// Number of files parsed
+    files_parsed: AtomicUsize,
+    /// Number of files that could not be read or parsed
+    files_failed: AtomicUsize,
+    /// Number of binary files skipped
+    files_binary: AtomicUsize,
+    /// Number of matches (or lines without matches, with `--invert-match`)
+    matches: AtomicUsize,
+}
+
+impl Stats {
+    fn add(counter: &AtomicUsize, n: usize) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn print<E: Write>(&self, stderr: &mut E, elapsed: Duration) {
+        let _ = writeln!(
+            stderr,
+            "{} directories walked",
+            self.dirs.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            stderr,
+            "{} files parsed",
+            self.files_parsed.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            stderr,
+            "{} files failed to read or parse",
+            self.files_failed.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            stderr,
+            "{} binary files skipped",
+            self.files_binary.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(stderr, "{} matches", self.matches.load(Ordering::Relaxed));
+        let _ = writeln!(stderr, "{:.3} seconds", elapsed.as_secs_f64());
+    }
 }
 
+/// `PATH` argument for searching stdin
+const STDIN_PATH: &str = "-";
+
+/// File path to show in matches in stdin
+const STDIN_LABEL: &str = "<stdin>";
+
+/// Number of bytes to check for NUL bytes to detect binary files
+const BINARY_SNIFF_LEN: u64 = 8 * 1024;
+
 fn main() {
     let stdout = std::io::stdout();
-    let mut stdout_lock = stdout.lock();
-    let ret = run(&mut stdout_lock, &mut std::env::args_os(), false);
+    let mut stdout = io::BufWriter::new(stdout.lock());
+    let ret = run(
+        &mut stdout,
+        &mut std::io::stderr(),
+        &mut std::io::stdin(),
+        std::env::args_os(),
+        false,
+    );
+    // `process::exit` doesn't run destructors, `run` flushes the buffer before returning
     std::process::exit(ret);
 }
 
-pub(crate) fn run<W, I, T>(stdout: &mut W, args_iter: I, assume_color_support: bool) -> i32
+/// Runs sg with command line arguments `args_iter`. Files given as `-` are read from `stdin`.
+/// `stdout` is flushed before returning. Statistics printed after searching (`--stats` and
+/// `--count-matches`) are written to `stderr`, warnings and errors are printed to the process
+/// stderr.
+///
+/// Like grep, returns 0 when something matched, 1 when nothing matched, and 2 on errors. With
+/// `--strict`, files that could not be read or parsed are also errors.
+pub(crate) fn run<W, E, R, I, T>(
+    stdout: &mut W,
+    stderr: &mut E,
+    stdin: &mut R,
+    args_iter: I,
+    assume_color_support: bool,
+) -> i32
 where
     W: Write,
+    E: Write,
+    R: Read,
     I: IntoIterator<Item = T>,
     T: Into<OsString> + Clone,
 {
+    let ret = run_unflushed(stdout, stderr, stdin, args_iter, assume_color_support);
+    match stdout.flush() {
+        // Output piped to e.g. `head` can be closed before we're done, that's not an error
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+            eprintln!("Unable to write output: {}", err);
+            2
+        }
+        _ => ret,
+    }
+}
+
+/// `run`, without flushing `stdout` at the end
+fn run_unflushed<W, E, R, I, T>(
+    stdout: &mut W,
+    stderr: &mut E,
+    stdin: &mut R,
+    args_iter: I,
+    assume_color_support: bool,
+) -> i32
+where
+    W: Write,
+    E: Write,
+    R: Read,
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let start_time = Instant::now();
+
     let cli::Args {
-        mut pattern,
-        path,
+        mut patterns,
+        pattern_files,
+        paths,
         column,
+        byte_offset,
+        context_symbol,
+        tab_width,
         nogroup,
-        mut nocolor,
+        heading,
+        line_number,
+        json,
+        json_pretty,
+        vimgrep,
+        null,
+        replace,
+        dry_run,
+        preview,
+        invert_match,
+        def,
+        files_with_matches,
+        files_without_matches,
+        count,
+        count_zero,
+        count_per_line,
+        max_count,
+        max_total_matches,
+        before_context,
+        after_context,
+        context_node_kinds,
+        color,
         casing,
         whole_word,
+        regex,
+        text_fallback,
+        fuzzy,
         node_kinds,
         matches,
         ignores,
+        includes,
+        excludes,
+        exclude_dirs,
+        glob_case_insensitive,
+        follow,
+        hidden,
+        sort,
+        parsers,
+        parser_dirs,
+        text,
+        encoding,
+        max_depth,
+        threads,
+        stats: print_stats,
+        progress,
+        count_matches,
+        strict,
+        cache,
+        watch,
+        query,
+        captures,
+        full_node,
+        each_capture,
+        list_files,
+        list_node_kinds,
+        list_queries,
+        debug_ast,
     } = match cli::parse_args_safe(args_iter) {
-        Err(err) => {
-            eprintln!("{}", err.message);
-            return 1;
-        }
+        Err(err) => match err.kind {
+            clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => {
+                let _ = writeln!(stdout, "{}", err.message);
+                return 0;
+            }
+            _ => {
+                eprintln!("{}", err.message);
+                return 2;
+            }
+        },
         Ok(args) => args,
     };
 
-    let mut lang: Option<(Language, &'static str)> = None;
+    let search_all = matches.is_present("all");
 
-    if matches.is_present("rust") {
-        lang = Some((unsafe { tree_sitter_rust() }, "rs"));
+    // Selected languages: name of the language, file extensions and tree-sitter languages for the
+    // extensions, and node kinds of the language
+    let mut langs = vec![];
+
+    if search_all || matches.is_present("rust") {
+        langs.push((
+            "rust",
+            vec![("rs", unsafe { tree_sitter_rust() })],
+            &RUST_NODE_KINDS,
+        ));
     }
 
-    if matches.is_present("ocaml") {
-        lang = Some((unsafe { tree_sitter_ocaml() }, "ml"));
+    if search_all || matches.is_present("ocaml") {
+        langs.push((
+            "ocaml",
+            vec![
+                ("ml", unsafe { tree_sitter_ocaml() }),
+                ("mli", unsafe { tree_sitter_ocaml_interface() }),
+            ],
+            &OCAML_NODE_KINDS,
+        ));
     }
 
-    if matches.is_present("dart") {
-        lang = Some((unsafe { tree_sitter_dart() }, "dart"));
+    if search_all || matches.is_present("dart") {
+        langs.push((
+            "dart",
+            vec![("dart", unsafe { tree_sitter_dart() })],
+            &DART_NODE_KINDS,
+        ));
     }
 
-    if matches.is_present("js") {
-        lang = Some((unsafe { tree_sitter_javascript() }, "js"));
+    if search_all || matches.is_present("js") {
+        let js = unsafe { tree_sitter_javascript() };
+        langs.push((
+            "js",
+            vec![("js", js), ("jsx", js), ("mjs", js), ("cjs", js)],
+            &JS_NODE_KINDS,
+        ));
     }
 
-    let (lang, lang_ext) = match lang {
-        None => {
-            eprintln!("No language specified; aborting.");
-            return 1;
+    if search_all || matches.is_present("ts") {
+        langs.push((
+            "ts",
+            vec![
+                ("ts", unsafe { tree_sitter_typescript() }),
+                ("tsx", unsafe { tree_sitter_tsx() }),
+            ],
+            &JS_NODE_KINDS,
+        ));
+    }
+
+    if search_all || matches.is_present("python") {
+        langs.push((
+            "python",
+            vec![("py", unsafe { tree_sitter_python() })],
+            &PYTHON_NODE_KINDS,
+        ));
+    }
+
+    if search_all || matches.is_present("go") {
+        langs.push((
+            "go",
+            vec![("go", unsafe { tree_sitter_go() })],
+            &GO_NODE_KINDS,
+        ));
+    }
+
+    if list_queries {
+        let lang_names: Vec<&str> = langs.iter().map(|(name, _, _)| *name).collect();
+        query::report_queries(stdout, &lang_names);
+        return 0;
+    }
+
+    // Names, file extensions, and languages of the parsers loaded with `--parser` and
+    // `--parser-dir`
+    let mut dynamic_langs: Vec<(String, Vec<String>, Language)> = Vec::with_capacity(parsers.len());
+    for parser in parsers {
+        match dynamic::load_parser(Path::new(&parser.path), parser.lang_sym.as_deref()) {
+            Ok((name, language)) => {
+                let ext = parser.ext.unwrap_or_else(|| name.clone());
+                dynamic_langs.push((name, vec![ext], language));
+            }
+            Err(err) => {
+                eprintln!("Unable to load parser {}: {}", parser.path, err);
+                return 2;
+            }
         }
-        Some(lang) => lang,
-    };
+    }
+    for dir in &parser_dirs {
+        let mut parser_dir = match dynamic::read_parser_dir(Path::new(dir)) {
+            Ok(parser_dir) => parser_dir,
+            Err(err) => {
+                eprintln!("Unable to read parser directory {}: {}", dir, err);
+                return 2;
+            }
+        };
+        // Other libraries in the directory may still be usable, skip the ones that can't be loaded
+        for lib in &parser_dir.libs {
+            match dynamic::load_parser(lib, None) {
+                Ok((name, language)) => {
+                    let exts = parser_dir
+                        .exts
+                        .remove(&name)
+                        .unwrap_or_else(|| vec![name.clone()]);
+                    dynamic_langs.push((name, exts, language));
+                }
+                Err(err) => {
+                    eprintln!("Unable to load parser {}: {}", lib.to_string_lossy(), err);
+                }
+            }
+        }
+    }
 
-    let mut parser = Parser::new();
-    parser.set_language(lang).unwrap();
+    if langs.is_empty() && dynamic_langs.is_empty() {
+        eprintln!("No language specified; aborting.");
+        return 2;
+    }
 
-    let path = path
-        .map(|s| s.into())
-        .unwrap_or_else(|| std::env::current_dir().unwrap());
+    // Without `--all` the last language is searched, with the languages loaded with `--parser`
+    if !search_all && !langs.is_empty() {
+        langs.drain(..langs.len() - 1);
+    }
+
+    for (name, exts, language) in &dynamic_langs {
+        langs.push((
+            name.as_str(),
+            exts.iter().map(|ext| (ext.as_str(), *language)).collect(),
+            &DYNAMIC_NODE_KINDS,
+        ));
+    }
 
-    let case_sensitive = match casing {
+    for file in &pattern_files {
+        match fs::read_to_string(file) {
+            Ok(contents) => patterns.extend(
+                contents
+                    .lines()
+                    .map(|line| line.trim_end_matches('\r'))
+                    .filter(|line| !line.is_empty())
+                    .map(|line| line.to_owned()),
+            ),
+            Err(err) => {
+                eprintln!("Unable to read pattern file {}: {}", file, err);
+                return 2;
+            }
+        }
+    }
+
+    // Case sensitivity of a pattern. With smart case a pattern is matched case sensitively when it
+    // has an uppercase character, independently of the other patterns.
+    let case_sensitive = |pattern: &str| match casing {
+        cli::Casing::Smart if regex => search::has_uppercase_literal(pattern),
         cli::Casing::Smart => pattern.chars().any(char::is_uppercase),
         cli::Casing::Sensitive => true,
-        cli::Casing::Insensitive => {
-            pattern = pattern.to_lowercase();
-            false
+        cli::Casing::Insensitive => false,
+    };
+
+    // When searching a query, case sensitivity of the `--capture` texts. With smart case matching
+    // is case sensitive when one of the texts has an uppercase character.
+    let captures_case_sensitive = match casing {
+        cli::Casing::Smart => captures
+            .iter()
+            .any(|(_, text)| text.chars().any(char::is_uppercase)),
+        cli::Casing::Sensitive => true,
+        cli::Casing::Insensitive => false,
+    };
+
+    // Source of the query to search, and where the source is from, for error messages
+    let query_source: Option<(String, String)> = match query {
+        None if !captures.is_empty() => {
+            eprintln!("--capture can only be used with --qs, --qn, or --query-file");
+            return 2;
+        }
+        None if full_node => {
+            eprintln!("--full-node can only be used with --qs, --qn, or --query-file");
+            return 2;
+        }
+        None if each_capture => {
+            eprintln!("--each-capture can only be used with --qs, --qn, or --query-file");
+            return 2;
         }
+        None => None,
+        Some(_) if langs.len() > 1 => {
+            eprintln!("Queries can only be searched in a single language");
+            return 2;
+        }
+        Some(cli::Query::String(source)) => Some((source, "--qs".to_owned())),
+        Some(cli::Query::Name(name)) => match query::load_named_query(langs[0].0, &name) {
+            Ok((source_name, source)) => Some((source, source_name)),
+            Err(err) => {
+                eprintln!("{}", err);
+                return 2;
+            }
+        },
+        Some(cli::Query::File(path)) => match fs::read_to_string(&path) {
+            Ok(source) => Some((source, path)),
+            Err(err) => {
+                eprintln!("Unable to read query file {}: {}", path, err);
+                return 2;
+            }
+        },
     };
 
-    if !nocolor && !assume_color_support && !std::io::stdout().is_terminal() {
-        nocolor = true;
+    let mut exts: Vec<ExtLang> = vec![];
+    // Error of the first extension the query could not be compiled for
+    let mut query_err: Option<String> = None;
+    for (_, lang_exts, node_kinds) in langs {
+        for (ext, language) in lang_exts {
+            let query = match &query_source {
+                None => None,
+                Some((source, source_name)) => {
+                    match query::Query::new(
+                        language,
+                        source,
+                        source_name,
+                        &captures,
+                        captures_case_sensitive,
+                        // With `--each-capture` captures are replaced like pattern matches
+                        replace.as_deref().filter(|_| !each_capture),
+                    ) {
+                        Ok(query) => Some(query),
+                        // Grammars of the extensions of a language can have different node kinds
+                        // (e.g. OCaml interfaces don't have definitions), don't search the
+                        // extensions the query is not valid for
+                        Err(err) => {
+                            query_err.get_or_insert(err);
+                            continue;
+                        }
+                    }
+                }
+            };
+            exts.push(ExtLang {
+                ext: ext.to_owned(),
+                language,
+                node_kinds,
+                query,
+            });
+        }
+    }
+
+    if exts.is_empty() {
+        if let Some(err) = query_err {
+            eprintln!("{}", err);
+            return 2;
+        }
+    }
+
+    let default_lang = if search_all || !parser_dirs.is_empty() {
+        None
+    } else {
+        Some(0)
+    };
+
+    let paths: Vec<PathBuf> = if paths.is_empty() {
+        if list_files || std::io::stdin().is_terminal() {
+            vec![std::env::current_dir().unwrap()]
+        } else {
+            vec![PathBuf::from(STDIN_PATH)]
+        }
+    } else {
+        paths.into_iter().map(PathBuf::from).collect()
+    };
+
+    // Patterns of only word characters can't match across tokens
+    let text_fallback = text_fallback
+        && patterns
+            .iter()
+            .any(|pattern| !pattern.chars().all(search::is_word_char));
+
+    // Regexes handle case insensitivity themselves. For literal patterns we lowercase the patterns
+    // and tokens.
+    let patterns = if regex {
+        let mut regexes = Vec::with_capacity(patterns.len());
+        for pattern in &patterns {
+            match search::RegexPattern::new(pattern, case_sensitive(pattern)) {
+                Ok(regex) => regexes.push(regex),
+                Err(err) => {
+                    eprintln!("Invalid regex: {}", err);
+                    return 2;
+                }
+            }
+        }
+        search::Patterns::Regex(regexes)
+    } else {
+        search::Patterns::Literal(
+            patterns
+                .into_iter()
+                .map(|pattern| {
+                    if case_sensitive(&pattern) {
+                        (pattern, true)
+                    } else {
+                        (search::lowercase(&pattern), false)
+                    }
+                })
+                .collect(),
+        )
+    };
+
+    let include = match build_glob_set(&includes, glob_case_insensitive) {
+        Ok(include) => include,
+        Err(err) => {
+            eprintln!("Invalid --glob glob: {}", err);
+            return 2;
+        }
+    };
+
+    let exclude = match build_glob_set(&excludes, glob_case_insensitive) {
+        Ok(exclude) => exclude,
+        Err(err) => {
+            eprintln!("Invalid --exclude glob: {}", err);
+            return 2;
+        }
+    };
+
+    let exclude_dir = match build_glob_set(&exclude_dirs, glob_case_insensitive) {
+        Ok(exclude_dir) => exclude_dir,
+        Err(err) => {
+            eprintln!("Invalid --exclude-dir glob: {}", err);
+            return 2;
+        }
+    };
+
+    let mut styles = colors::Styles::default();
+    if let Some(specs) = std::env::var_os("SG_COLORS") {
+        for err in styles.update(&specs.to_string_lossy()) {
+            eprintln!("{}", err);
+        }
     }
 
+    let color = match color {
+        cli::Color::Always => true,
+        cli::Color::Never => false,
+        cli::Color::Auto => {
+            std::env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+                && (assume_color_support || std::io::stdout().is_terminal())
+        }
+    };
+
     let cfg = Cfg {
-        color: !nocolor,
+        color,
         column,
+        byte_offset,
+        context_symbol,
+        tab_width,
         group: !nogroup,
-        pattern,
+        heading,
+        line_number,
+        json,
+        json_pretty,
+        vimgrep,
+        null,
+        replace,
+        dry_run,
+        preview,
+        invert_match,
+        def,
+        full_node,
+        each_capture,
+        list_node_kinds,
+        debug_ast,
+        files_with_matches,
+        files_without_matches,
+        count,
+        count_zero,
+        count_per_line,
+        max_count,
+        max_total_matches,
+        before_context,
+        after_context,
+        context_node_kinds,
+        patterns,
         node_kinds,
-        case_sensitive,
         whole_word,
-        parser: RefCell::new(parser),
-        ext: lang_ext,
-        file_path_style: ansi_term::Colour::Green.bold(),
-        line_num_style: ansi_term::Colour::Yellow.bold(),
-        match_style: ansi_term::Colour::Black.on(ansi_term::Color::Yellow),
+        text_fallback,
+        fuzzy,
+        exts,
+        default_lang,
+        include,
+        exclude,
+        exclude_dir,
+        follow,
+        hidden,
+        sort,
+        text,
+        encoding,
+        tree_cache: if cache || watch {
+            Some(cache::TreeCache::new(cache::TREE_CACHE_CAPACITY))
+        } else {
+            None
+        },
+        max_depth,
+        file_path_style: styles.file_path,
+        line_num_style: styles.line_num,
+        match_style: styles.match_,
+        removed_style: styles.removed,
+        added_style: styles.added,
     };
 
-    let mut first = true;
+    let stats = Stats::default();
+
+    if watch && paths.iter().any(|path| path == Path::new(STDIN_PATH)) {
+        eprintln!("--watch can't be used when searching stdin");
+        return 2;
+    }
 
-    if path.is_dir() {
-        walk_path(stdout, &path, &cfg, &mut first, &ignores);
+    // Only print progress when stderr is a terminal, and not when printing JSON for other programs
+    let progress: Option<Arc<Progress>> = if progress && !json && io::stderr().is_terminal() {
+        Some(Arc::new(Progress::default()))
     } else {
-        search_file(stdout, &path, &cfg, &mut first);
+        None
+    };
+    let progress_thread = progress.clone().map(progress::start);
+
+    let files = collect_files(&paths, &cfg, &ignores, &stats, progress.as_deref());
+
+    if list_files {
+        drop(progress_thread);
+        for file in &files {
+            report::report_path(stdout, &cfg, file);
+        }
+        return if files.is_empty() { 1 } else { 0 };
     }
 
-    0
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool,
+        Err(err) => {
+            eprintln!("Unable to create thread pool: {}", err);
+            return 2;
+        }
+    };
+
+    // Read stdin before searching, as files are searched in the thread pool
+    let mut stdin_contents = String::new();
+    if files.iter().any(|file| file == Path::new(STDIN_PATH)) {
+        let mut bytes = vec![];
+        match stdin.read_to_end(&mut bytes).and_then(|_| {
+            let encoding = encoding::Encoding::detect(&bytes, cfg.encoding);
+            encoding.decode(bytes)
+        }) {
+            Ok(contents) => stdin_contents = contents,
+            Err(err) => {
+                eprintln!("Unable to read stdin: {}", err);
+                return 2;
+            }
+        }
+    }
+
+    search_files(
+        stdout,
+        &pool,
+        &files,
+        &cfg,
+        &stats,
+        progress.as_deref(),
+        &stdin_contents,
+    );
+    let _ = stdout.flush();
+    drop(progress_thread);
+
+    if print_stats {
+        stats.print(stderr, start_time.elapsed());
+    } else if count_matches {
+        let _ = writeln!(stderr, "{} matches", stats.matches.load(Ordering::Relaxed));
+    }
+
+    if watch {
+        return watch::watch(stdout, &pool, &paths, &cfg, &ignores);
+    }
+
+    if strict && stats.files_failed.load(Ordering::Relaxed) > 0 {
+        2
+    } else if stats.matches.load(Ordering::Relaxed) > 0 || cfg.list_node_kinds || cfg.debug_ast {
+        0
+    } else {
+        1
+    }
 }
 
-fn walk_path<W: Write>(
-    stdout: &mut W,
+/// Collect files to search in `paths`, walking directories
+fn collect_files(
+    paths: &[PathBuf],
+    cfg: &Cfg,
+    ignores: &[String],
+    stats: &Stats,
+    progress: Option<&Progress>,
+) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = vec![];
+
+    // With `--follow`, canonical paths of the directories walked, to avoid symlink cycles
+    let mut visited_dirs: HashSet<PathBuf> = HashSet::new();
+
+    for path in paths {
+        if path == Path::new(STDIN_PATH) {
+            files.push(path.clone());
+            continue;
+        }
+
+        if !path.exists() {
+            eprintln!("{} does not exist, skipping", path.to_string_lossy());
+            continue;
+        }
+
+        if path.is_dir() {
+            if cfg.follow {
+                if let Ok(canonical_path) = path.canonicalize() {
+                    visited_dirs.insert(canonical_path);
+                }
+            }
+            walk_path(
+                path,
+                path,
+                0,
+                cfg,
+                ignores,
+                stats,
+                progress,
+                &mut visited_dirs,
+                &mut files,
+            );
+        } else {
+            files.push(path.clone());
+        }
+    }
+
+    files
+}
+
+fn build_glob_set(globs: &[String], case_insensitive: bool) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(
+            GlobBuilder::new(glob)
+                .case_insensitive(case_insensitive)
+                .build()?,
+        );
+    }
+    builder.build()
+}
+
+/// Collect files to search in directory `path`. `root` is the directory given as `PATH`, paths
+/// relative to it are matched against `--glob`, `--exclude`, and `--exclude-dir` globs. `depth` is
+/// the number of directories between `root` and `path`.
+fn walk_path(
+    root: &Path,
     path: &Path,
+    depth: usize,
     cfg: &Cfg,
-    first: &mut bool,
     ignores: &[String],
+    stats: &Stats,
+    progress: Option<&Progress>,
+    visited_dirs: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
 ) {
+    Stats::add(&stats.dirs, 1);
+    if let Some(progress) = progress {
+        progress.set_dir(path);
+    }
+
     let dir_contents = match fs::read_dir(path) {
         Ok(ok) => ok,
         Err(err) => {
@@ -171,17 +1179,31 @@ fn walk_path<W: Write>(
         }
     };
 
-    'dir_loop: for file in dir_contents {
-        let file = match file {
-            Ok(ok) => ok,
-            Err(err) => {
-                eprintln!("Unable to read dir entry: {}", err);
-                continue;
-            }
-        };
+    let mut entries: Vec<fs::DirEntry> = vec![];
+    for entry in dir_contents {
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(err) => eprintln!("Unable to read dir entry: {}", err),
+        }
+    }
+
+    match cfg.sort {
+        None => {}
+        Some(cli::Sort::Path) => entries.sort_by_key(|entry| entry.file_name()),
+        Some(cli::Sort::Modified) => entries.sort_by_cached_key(|entry| {
+            fs::metadata(entry.path())
+                .and_then(|meta| meta.modified())
+                .ok()
+        }),
+    }
 
+    'dir_loop: for file in entries {
         let full_path = file.path();
 
+        if !cfg.hidden && file.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+
         if !ignores.is_empty() {
             let full_path_str = full_path.to_string_lossy();
             for ignore in ignores {
@@ -191,7 +1213,14 @@ fn walk_path<W: Write>(
             }
         }
 
-        let meta = match file.metadata() {
+        // `DirEntry::metadata` does not follow symlinks, `fs::metadata` does
+        let meta = match file.metadata().and_then(|meta| {
+            if meta.file_type().is_symlink() {
+                fs::metadata(&full_path)
+            } else {
+                Ok(meta)
+            }
+        }) {
             Ok(ok) => ok,
             Err(err) => {
                 eprintln!(
@@ -203,64 +1232,442 @@ fn walk_path<W: Write>(
             }
         };
 
+        // Without `--follow` skip symlinks to directories. With `--follow` skip directories we
+        // already walked, to avoid cycles.
+        if meta.is_dir() && file.file_type().is_ok_and(|ty| ty.is_symlink()) {
+            if !cfg.follow {
+                continue;
+            }
+            match full_path.canonicalize() {
+                Ok(canonical_path) => {
+                    if !visited_dirs.insert(canonical_path) {
+                        continue;
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Unable to resolve {}: {}", full_path.to_string_lossy(), err);
+                    continue;
+                }
+            }
+        }
+
+        let relative_path = full_path.strip_prefix(root).unwrap_or(&full_path);
+        let file_name = file.file_name();
+        let glob_match =
+            |globs: &GlobSet| globs.is_match(relative_path) || globs.is_match(&file_name);
+
         if meta.is_dir() {
-            walk_path(stdout, &full_path, cfg, first, ignores);
+            if !glob_match(&cfg.exclude_dir) && cfg.max_depth.is_none_or(|max| depth < max) {
+                walk_path(
+                    root,
+                    &full_path,
+                    depth + 1,
+                    cfg,
+                    ignores,
+                    stats,
+                    progress,
+                    visited_dirs,
+                    files,
+                );
+            }
         } else if let Some(ext) = full_path.extension() {
-            if ext == cfg.ext {
-                search_file(stdout, &full_path, cfg, first);
+            if cfg.exts.iter().any(|ext_lang| ext_lang.matches(ext))
+                && (cfg.include.is_empty() || glob_match(&cfg.include))
+                && !glob_match(&cfg.exclude)
+            {
+                files.push(full_path);
             }
         }
     }
 }
 
-fn search_file<W: Write>(stdout: &mut W, path: &Path, cfg: &Cfg, first: &mut bool) {
-    let contents = match fs::read_to_string(path) {
-        Ok(ok) => ok,
-        Err(err) => {
-            eprintln!("Unable to read {}: {}", path.to_string_lossy(), err);
-            return;
+/// Search `files` in parallel in `pool`. Output of each file is written to `stdout` in the order
+/// of `files`, as soon as outputs of the file and the files before it are ready. `stdin` is the
+/// contents of the file `-`.
+///
+/// With `progress`, the output of each file is flushed, so that the progress line is not printed
+/// in the middle of the output.
+fn search_files<W: Write>(
+    stdout: &mut W,
+    pool: &rayon::ThreadPool,
+    files: &[PathBuf],
+    cfg: &Cfg,
+    stats: &Stats,
+    progress: Option<&Progress>,
+    stdin: &str,
+) {
+    // Sends file index, output of the file, whether anything was printed for a match (lines or
+    // header) in the output, and number of matches in the file
+    let (sender, receiver) = mpsc::channel::<(usize, Vec<u8>, bool, usize)>();
+
+    // Set when `--max-total-matches` matches are printed, to skip the remaining files
+    let done = AtomicBool::new(false);
+
+    // Number of matches printed so far, for `--max-total-matches`
+    let mut total_matches = 0;
+
+    pool.in_place_scope(|scope| {
+        let done = &done;
+        scope.spawn(move |_| {
+            files.par_iter().enumerate().for_each_init(
+                || (sender.clone(), Parser::new()),
+                |(sender, parser), (file_idx, path)| {
+                    if done.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    if let Some(progress) = progress {
+                        if let Some(dir) = path.parent() {
+                            progress.set_dir(dir);
+                        }
+                    }
+                    let mut output = vec![];
+                    let mut first = true;
+                    let num_matches = search_file(
+                        &mut output,
+                        path,
+                        cfg,
+                        stats,
+                        parser,
+                        stdin,
+                        cfg.max_count,
+                        &mut first,
+                    );
+                    if let Some(progress) = progress {
+                        progress.file_searched();
+                    }
+                    let _ = sender.send((file_idx, output, !first, num_matches));
+                },
+            );
+        });
+
+        // Outputs of files that are ready before the outputs of the files before them
+        let mut pending: BTreeMap<usize, (Vec<u8>, bool, usize)> = BTreeMap::new();
+        let mut next_file_idx = 0;
+
+        // Separate file groups with an empty line
+        let mut first = true;
+
+        for (file_idx, output, header_printed, num_matches) in receiver {
+            pending.insert(file_idx, (output, header_printed, num_matches));
+            while let Some((mut output, mut header_printed, mut num_matches)) =
+                pending.remove(&next_file_idx)
+            {
+                if let Some(max_total_matches) = cfg.max_total_matches {
+                    let remaining = max_total_matches - total_matches;
+                    if num_matches > remaining {
+                        // Search the file again, reporting only the remaining matches
+                        stats.matches.fetch_sub(num_matches, Ordering::Relaxed);
+                        output.clear();
+                        let mut file_first = true;
+                        num_matches = search_file(
+                            &mut output,
+                            &files[next_file_idx],
+                            cfg,
+                            &Stats::default(),
+                            &mut Parser::new(),
+                            stdin,
+                            Some(remaining),
+                            &mut file_first,
+                        );
+                        header_printed = !file_first;
+                        Stats::add(&stats.matches, num_matches);
+                    }
+                }
+
+                let mut write_output = || {
+                    if header_printed && cfg.group {
+                        if first {
+                            first = false;
+                        } else {
+                            let _ = writeln!(stdout);
+                        }
+                    }
+                    let _ = stdout.write_all(&output);
+                    if progress.is_some() {
+                        let _ = stdout.flush();
+                    }
+                };
+                match progress {
+                    Some(progress) if !output.is_empty() => progress.suspend(write_output),
+                    _ => write_output(),
+                }
+                next_file_idx += 1;
+
+                total_matches += num_matches;
+                if cfg.max_total_matches == Some(total_matches) {
+                    done.store(true, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+    });
+
+    // Files searched in parallel with the last printed files added their matches to the stats,
+    // but their outputs were dropped. Count only the printed matches.
+    if cfg.max_total_matches == Some(total_matches) {
+        stats.matches.store(total_matches, Ordering::Relaxed);
+    }
+}
+
+/// Read the file at `path`. Returns `None` when the file is binary (has a NUL byte in the first
+/// `BINARY_SNIFF_LEN` bytes) and `text` is not set. Binary files are not read fully.
+///
+/// Files starting with a byte order mark are decoded as UTF-8 or UTF-16, other files are decoded
+/// as `encoding` (`--encoding`, see `encoding`). The returned contents don't have the BOM.
+fn read_file(
+    path: &Path,
+    text: bool,
+    encoding: Option<&'static encoding_rs::Encoding>,
+) -> io::Result<Option<String>> {
+    let mut file = fs::File::open(path)?;
+    let mut bytes = vec![];
+    (&mut file).take(BINARY_SNIFF_LEN).read_to_end(&mut bytes)?;
+    let encoding = encoding::Encoding::detect(&bytes, encoding);
+    if !text && !encoding.is_utf16() && bytes.contains(&0) {
+        return Ok(None);
+    }
+    file.read_to_end(&mut bytes)?;
+    encoding.decode(bytes).map(Some)
+}
+
+/// Search the file at `path` and report at most `max_count` matches. Returns the number of
+/// reported matches.
+fn search_file<W: Write>(
+    stdout: &mut W,
+    path: &Path,
+    cfg: &Cfg,
+    stats: &Stats,
+    parser: &mut Parser,
+    stdin: &str,
+    max_count: Option<usize>,
+    first: &mut bool,
+) -> usize {
+    let is_stdin = path == Path::new(STDIN_PATH);
+
+    // With `--cache`, modification time of the file, to find the file in the cache
+    let modified: Option<SystemTime> = match &cfg.tree_cache {
+        Some(_) if !is_stdin => fs::metadata(path).and_then(|meta| meta.modified()).ok(),
+        _ => None,
+    };
+
+    let cached: Option<(Arc<str>, Tree)> = cfg
+        .tree_cache
+        .as_ref()
+        .zip(modified)
+        .and_then(|(cache, modified)| cache.get(path, modified));
+
+    let (path, contents): (&Path, Arc<str>) = if is_stdin {
+        (Path::new(STDIN_LABEL), stdin.into())
+    } else if let Some((contents, _)) = &cached {
+        (path, contents.clone())
+    } else {
+        match read_file(path, cfg.text, cfg.encoding) {
+            Ok(Some(contents)) => (path, contents.into()),
+            Ok(None) => {
+                eprintln!("Skipping binary file {}", path.to_string_lossy());
+                Stats::add(&stats.files_binary, 1);
+                return 0;
+            }
+            Err(err) => {
+                eprintln!("Unable to read {}: {}", path.to_string_lossy(), err);
+                Stats::add(&stats.files_failed, 1);
+                return 0;
+            }
         }
     };
 
-    let tree = match cfg.parser.borrow_mut().parse(contents.as_bytes(), None) {
-        Some(ok) => ok,
+    let ext_lang = match cfg.language(path) {
+        Some(ext_lang) => ext_lang,
         None => {
-            eprintln!("Unable to parse {}", path.to_string_lossy());
-            return;
+            eprintln!(
+                "Unable to determine language of {}, skipping",
+                path.to_string_lossy()
+            );
+            return 0;
+        }
+    };
+
+    let tree = match cached {
+        Some((_, tree)) => tree,
+        None => {
+            parser.set_language(ext_lang.language).unwrap();
+
+            let tree = match parser.parse(contents.as_bytes(), None) {
+                Some(ok) => ok,
+                None => {
+                    eprintln!("Unable to parse {}", path.to_string_lossy());
+                    Stats::add(&stats.files_failed, 1);
+                    return 0;
+                }
+            };
+            Stats::add(&stats.files_parsed, 1);
+
+            if let (Some(cache), Some(modified)) = (&cfg.tree_cache, modified) {
+                cache.insert(path, modified, contents.clone(), tree.clone());
+            }
+
+            tree
         }
     };
 
     let root = tree.root_node();
-    walk_ast(stdout, path, cfg, &contents, root, first);
+    if cfg.list_node_kinds {
+        debug::report_node_kinds(stdout, cfg, path, root, first);
+        return 0;
+    }
+
+    if cfg.debug_ast {
+        debug::report_tree(stdout, cfg, path, root, first);
+        return 0;
+    }
+
+    match &ext_lang.query {
+        Some(query) => query::search_query(
+            stdout, path, cfg, stats, query, &contents, root, max_count, first,
+        ),
+        None => walk_ast(
+            stdout,
+            path,
+            cfg,
+            stats,
+            ext_lang.node_kinds,
+            &contents,
+            root,
+            max_count,
+            first,
+        ),
+    }
+}
+
+/// Byte range of the name of the innermost definition containing `node` (see
+/// `LangNodeKinds::symbol_name_fields`)
+fn enclosing_symbol(node: Node, lang_node_kinds: &LangNodeKinds) -> Option<(usize, usize)> {
+    let mut parent = node.parent();
+    while let Some(node) = parent {
+        for (kind, name_field) in lang_node_kinds.symbol_name_fields {
+            if node.kind() == *kind {
+                if let Some(name) = node.child_by_field_name(name_field) {
+                    return Some((name.start_byte(), name.end_byte()));
+                }
+            }
+        }
+        parent = node.parent();
+    }
+    None
+}
+
+/// 0-based line range (end exclusive) of the innermost node containing `node` (or `node` itself)
+/// with one of the kinds `kinds`
+fn enclosing_node_lines(node: Node, kinds: &[String]) -> Option<(usize, usize)> {
+    let mut node = Some(node);
+    while let Some(ancestor) = node {
+        if kinds.iter().any(|kind| kind == ancestor.kind()) {
+            return Some((
+                ancestor.start_position().row,
+                ancestor.end_position().row + 1,
+            ));
+        }
+        node = ancestor.parent();
+    }
+    None
+}
+
+/// Whether the leaf node `node` is a keyword (see `LangNodeKinds::keyword_kinds`)
+fn is_keyword(node: Node, lang_node_kinds: &LangNodeKinds) -> bool {
+    let kind = node.kind();
+    if node.is_named() {
+        lang_node_kinds.keyword_kinds.contains(&kind)
+    } else {
+        !kind.is_empty()
+            && kind
+                .chars()
+                .all(|char| char.is_alphanumeric() || char == '_')
+    }
+}
+
+/// Whether the leaf node `node` is an identifier or a number literal, which are searched with
+/// `--kind identifier` (see `LangNodeKinds::identifier_kinds`). Punctuation and operators are not
+/// identifiers.
+fn is_identifier(node: Node, lang_node_kinds: &LangNodeKinds) -> bool {
+    let kind = node.kind();
+    node.is_named()
+        && (lang_node_kinds.identifier_kinds.is_empty()
+            || lang_node_kinds.identifier_kinds.contains(&kind)
+            || lang_node_kinds.number_kinds.contains(&kind))
+}
+
+/// Whether `node` is the name of a function definition (see `LangNodeKinds::def_name_fields`)
+fn is_def_name(node: Node, lang_node_kinds: &LangNodeKinds) -> bool {
+    let parent = match node.parent() {
+        Some(parent) => parent,
+        None => return false,
+    };
+    lang_node_kinds
+        .def_name_fields
+        .iter()
+        .any(|(def_kind, name_field)| {
+            parent.kind() == *def_kind && parent.child_by_field_name(name_field) == Some(node)
+        })
 }
 
 fn walk_ast<W: Write>(
     stdout: &mut W,
     path: &Path,
     cfg: &Cfg,
+    stats: &Stats,
+    lang_node_kinds: &LangNodeKinds,
     contents: &str,
     node: Node,
+    max_count: Option<usize>,
     first: &mut bool,
-) {
+) -> usize {
     let bytes = contents.as_bytes();
 
-    // TODO: Generate this lazily
-    let lines: Vec<&str> = contents.lines().collect();
+    let line_starts: Vec<usize> = search::line_starts(contents);
 
-    let mut work = vec![node];
+    // Nodes to visit, and whether their parents are comments or literals
+    let mut work = vec![(node, false)];
 
-    // Did we print the file name? Only used with `cfg.group`
-    let mut header_printed = false;
+    // Matches in the file, in source order
+    let mut matches: Vec<search::Match> = vec![];
 
-    while let Some(node) = work.pop() {
+    // With `--invert-match`: lines with at least one searched token
+    let mut searched_lines: BTreeSet<usize> = BTreeSet::new();
+
+    while let Some((node, in_literal)) = work.pop() {
         let node_kind = node.kind();
 
         let mut search = false;
-        let is_comment = node_kind == "block_comment" || node_kind == "line_comment";
+        let is_comment = lang_node_kinds.comment_kinds.contains(&node_kind);
+        let is_string = lang_node_kinds.string_kinds.contains(&node_kind);
+        let is_number = lang_node_kinds.number_kinds.contains(&node_kind);
+        let is_char = lang_node_kinds.char_kinds.contains(&node_kind);
         search |= is_comment && cfg.node_kinds.comment;
-        search |= node_kind == "string_literal" && cfg.node_kinds.string;
-
-        let is_id = !is_comment && node.child_count() == 0 && cfg.node_kinds.identifier;
+        search |= is_comment
+            && cfg.node_kinds.doc_comment
+            && node
+                .utf8_text(bytes)
+                .is_ok_and(|text| (lang_node_kinds.is_doc_comment)(node_kind, text));
+        search |= is_string && cfg.node_kinds.string;
+        search |= is_number && cfg.node_kinds.number;
+        search |= is_char && cfg.node_kinds.char;
+
+        let search_literal = search;
+
+        // Tokens in comments and literals (e.g. string contents in JavaScript) are not identifiers,
+        // but we still visit children of literals to search identifiers in e.g. template
+        // substitutions in JavaScript and interpolations in Python
+        let is_literal = is_comment || is_string || is_char;
+        let is_token = (!(is_literal || in_literal) || lang_node_kinds.literal_tokens_are_ids)
+            && node.child_count() == 0;
+        let is_id = is_token
+            && if is_keyword(node, lang_node_kinds) {
+                cfg.node_kinds.keyword && !cfg.def
+            } else {
+                is_identifier(node, lang_node_kinds)
+                    && cfg.node_kinds.identifier
+                    && (!cfg.def || is_def_name(node, lang_node_kinds))
+            };
         search |= is_id;
 
         if search {
@@ -276,303 +1683,222 @@ fn walk_ast<W: Write>(
                 Ok(token_str) => token_str,
             };
 
-            for match_byte_idx in match_token(
-                token_str,
-                &cfg.pattern,
-                is_id,
-                cfg.whole_word,
-                cfg.case_sensitive,
-            ) {
-                report_match(
-                    stdout,
-                    cfg,
-                    path,
-                    &node,
-                    token_str,
-                    &lines,
-                    match_byte_idx,
-                    &mut header_printed,
-                    first,
-                );
+            if cfg.invert_match {
+                searched_lines.extend(node.start_position().row..=node.end_position().row);
             }
-        }
 
-        let mut cursor = node.walk();
-        for child in node.children(&mut cursor) {
-            work.push(child);
-        }
-    }
-}
+            // Search character literals without the quotes, like strings
+            let (search_str, search_str_offset) = if is_char {
+                search::char_literal_contents(token_str)
+            } else {
+                (token_str, 0)
+            };
 
-fn get_token_line_col(token: &str, column0: usize, mut byte_idx: usize) -> (usize, usize, usize) {
-    let mut chars = token.chars().peekable();
+            let token_matches = search::find_matches(search_str, cfg, is_id && !search_literal);
 
-    let mut line = 0;
-    let mut col = column0;
-    let mut col_byte_idx = 0;
+            let symbol = if cfg.context_symbol && !token_matches.is_empty() {
+                enclosing_symbol(node, lang_node_kinds)
+            } else {
+                None
+            };
+
+            let context_lines = if !cfg.context_node_kinds.is_empty() && !token_matches.is_empty() {
+                enclosing_node_lines(node, &cfg.context_node_kinds)
+            } else {
+                None
+            };
 
-    while byte_idx != 0 {
-        let c = chars.next().unwrap();
-        byte_idx -= c.len_utf8();
-        if c == '\r' {
-            if let Some('\n') = chars.peek() {
-                let _ = chars.next(); // consume '\n'
-                byte_idx -= '\n'.len_utf8();
+            for (pattern_idx, (match_begin, match_end)) in token_matches {
+                matches.push(search::Match {
+                    symbol,
+                    context_lines,
+                    pattern: Some(pattern_idx),
+                    ..search::Match::new(
+                        &node,
+                        token_str,
+                        contents,
+                        &line_starts,
+                        search_str_offset + match_begin,
+                        match_end - match_begin,
+                        cfg.tab_width,
+                    )
+                });
             }
-            line += 1;
-            col = 0;
-            col_byte_idx = 0;
-        } else if c == '\n' {
-            line += 1;
-            col = 0;
-            col_byte_idx = 0;
-        } else {
-            col += 1;
-            col_byte_idx += c.len_utf8();
-        }
-    }
 
-    (line, col, col_byte_idx)
-}
+            // With `-l` and `-L` we only need to know whether the file has a match
+            if (cfg.files_with_matches || cfg.files_without_matches)
+                && !cfg.invert_match
+                && !matches.is_empty()
+            {
+                break;
+            }
 
-fn check_word_bounds(text: &str, match_begin: usize, match_end: usize) -> bool {
-    if let Some(char) = text[..match_begin].chars().next_back() {
-        if char.is_alphabetic() {
-            return false;
+            // With `--max-count`, stop once we have enough matches. With `--text-fallback`, line
+            // matches are merged with these in source order before the limit is applied.
+            if !cfg.invert_match
+                && !cfg.text_fallback
+                && max_count.is_some_and(|max| matches.len() >= max)
+            {
+                break;
+            }
         }
-    }
 
-    if let Some(char) = text[match_end..].chars().next() {
-        if char.is_alphabetic() {
-            return false;
-        }
+        // Push children in reverse order so that nodes are visited in source order
+        let mut cursor = node.walk();
+        let children_start = work.len();
+        work.extend(node.children(&mut cursor).map(|child| (child, is_literal)));
+        work[children_start..].reverse();
     }
 
-    true
-}
-
-/// Returns byte indices of matches of `pattern` in `token`
-fn match_token(
-    token: &str,
-    pattern: &str,
-    is_id: bool,
-    whole_word: bool,
-    case_sensitive: bool,
-) -> Vec<usize> {
-    #[cfg(debug_assertions)]
-    if !case_sensitive {
-        assert_eq!(pattern, pattern.to_lowercase());
+    if cfg.text_fallback {
+        matches.extend(text_fallback_matches(
+            node,
+            cfg,
+            lang_node_kinds,
+            contents,
+            &line_starts,
+        ));
     }
 
-    let token: Cow<'_, str> = if !case_sensitive {
-        Cow::Owned(token.to_lowercase())
+    // Report matches in source order. Literals nested in searched literals (e.g. strings in
+    // template substitutions) are searched both as a part of the outer literal and on their own,
+    // remove the duplicate matches.
+    matches.sort_by_key(|match_| (match_.byte_offset, match_.len));
+    matches.dedup_by_key(|match_| match_.byte_range());
+
+    // Lines of the file, to report the matches. Most files searched don't have any matches, so
+    // only split the file when there is something to report.
+    let lines: Vec<&str> = if matches.is_empty() && searched_lines.is_empty() {
+        vec![]
     } else {
-        Cow::Borrowed(token)
+        contents.lines().collect()
     };
 
-    // Special case for whole-word identifiers: don't look at word bounds, expect the whole token
-    // to match
-    if is_id && whole_word {
-        return if token == pattern { vec![0] } else { vec![] };
+    // With `--invert-match`, report the searched lines without matches as (empty) matches
+    if cfg.invert_match {
+        for match_ in &matches {
+            searched_lines.remove(&match_.line());
+        }
+        matches = searched_lines
+            .into_iter()
+            .filter(|line| *line < lines.len())
+            .map(|line| search::Match::line_start(&line_starts, line))
+            .collect();
     }
 
-    // In other cases we'll find the pattern in the token (which may occur multiple times) and
-    // check word boundaries when necessary
-    token
-        .match_indices(pattern)
-        .flat_map(|(match_begin, _)| {
-            if whole_word
-                && !check_word_bounds(token.as_ref(), match_begin, match_begin + pattern.len())
-            {
-                None.into_iter()
-            } else {
-                Some(match_begin).into_iter()
+    report_file_matches(
+        stdout,
+        path,
+        cfg,
+        stats,
+        contents,
+        &lines,
+        &[],
+        matches,
+        max_count,
+        first,
+    )
+}
+
+/// With `--text-fallback`, matches of the pattern in lines of the file spanning multiple tokens.
+/// Matches in single tokens, comments, and literals are found when searching the tokens.
+fn text_fallback_matches(
+    root: Node,
+    cfg: &Cfg,
+    lang_node_kinds: &LangNodeKinds,
+    contents: &str,
+    line_starts: &[usize],
+) -> Vec<search::Match> {
+    let mut matches = vec![];
+
+    for (line, &line_start) in line_starts.iter().enumerate() {
+        let line_end = line_starts
+            .get(line + 1)
+            .map_or(contents.len(), |next_line_start| next_line_start - 1);
+        let line_str = contents[line_start..line_end].trim_end_matches('\r');
+
+        for (pattern_idx, (match_begin, match_end)) in search::find_matches(line_str, cfg, false) {
+            let (start, end) = (line_start + match_begin, line_start + match_end);
+            let node = match root.descendant_for_byte_range(start, end) {
+                Some(node) => node,
+                None => continue,
+            };
+            if start == end || node.child_count() == 0 || in_literal(node, lang_node_kinds) {
+                continue;
             }
-        })
-        .collect()
+
+            matches.push(search::Match {
+                symbol: if cfg.context_symbol {
+                    enclosing_symbol(node, lang_node_kinds)
+                } else {
+                    None
+                },
+                context_lines: enclosing_node_lines(node, &cfg.context_node_kinds),
+                node_kind: Some(node.kind()),
+                pattern: Some(pattern_idx),
+                ..search::Match::at(contents, line_starts, start, end - start, cfg.tab_width)
+            });
+        }
+    }
+
+    matches
 }
 
-/// # Arguments
-///
-/// * `stdout`: A `Write` implementation to write the report. This function does not use process
-///   stdout directly, writes to this instead.
-///
-/// * `cfg`: User configuration (derived from defaults and CLI args)
-///
-/// * `path`: Path of the file with the match. Will be printed directly to `stdout`.
-///
-/// * `node`: tree-sitter node with the match. If you convert this node to string with
-///   `node.utf8_text()` (use `token_str`), then the searched term will be in the string.
-///
-/// * `token_str`: `node.utf8_text()`
-///
-/// * `lines`: Lines of the file that `node` is in (the file at `path`).
-///
-/// * `match_byte_idx`: Byte indices (in `token_str`) of matches of the searched term in
-///   `token_str`.
-///
-/// * `header_printed`: Whether we've printed a header for the matches in the current file. When
-///   grouping matches (default, without `--nogroup`) we print one header per file. With
-///   `--nogroup` we print the header for each match.
-///
-/// * `first`: When grouping (default, without `--nogroup`) we print one header per file, so we
-///   keep track of whether the match is the first match. If it is, then we print the header
-///   without `--nogroup`.
-///
-fn report_match<W: Write>(
+/// Whether `node` is a comment or a literal, or in one
+fn in_literal(node: Node, lang_node_kinds: &LangNodeKinds) -> bool {
+    std::iter::successors(Some(node), Node::parent).any(|node| {
+        let kind = node.kind();
+        lang_node_kinds.comment_kinds.contains(&kind)
+            || lang_node_kinds.string_kinds.contains(&kind)
+            || lang_node_kinds.char_kinds.contains(&kind)
+    })
+}
+
+/// Report at most `max_count` of `matches` in the file at `path`, or replace them with
+/// `--replace`. `matches` should be sorted. `capture_names` are the names of the query captures
+/// of the matches, with `--each-capture`. Returns the number of reported matches.
+pub(crate) fn report_file_matches<W: Write>(
     stdout: &mut W,
-    cfg: &Cfg,
     path: &Path,
-    node: &Node,
-    token_str: &str,
+    cfg: &Cfg,
+    stats: &Stats,
+    contents: &str,
     lines: &[&str],
-    match_byte_idx: usize,
-    header_printed: &mut bool,
+    capture_names: &[String],
+    mut matches: Vec<search::Match>,
+    max_count: Option<usize>,
     first: &mut bool,
-) {
-    let pos = node.start_position();
-
-    let (token_line, column, mut column_byte) =
-        get_token_line_col(token_str, pos.column, match_byte_idx);
-
-    // If we didn't skip any lines, `column_byte` need to be added to the beginning of the token
-    if token_line == 0 {
-        // Find byte index of the line `node` starts
-        let node_row: usize = pos.row;
-        // TODO: Cache line start byte indices to avoid repeatedly computing this for matches in
-        // the same file
-        // TODO: This assumes one-character line ending
-        let token_line_byte_idx: usize = lines[0..node_row].iter().map(|s| s.len() + 1).sum();
-        column_byte += node.start_byte() - token_line_byte_idx;
+) -> usize {
+    if let Some(max_count) = max_count {
+        matches.truncate(max_count);
     }
 
-    let column_byte = column_byte;
-
-    let line = pos.row + token_line;
+    Stats::add(&stats.matches, matches.len());
 
-    // Print header (if grouping)
-    if !*header_printed && cfg.group {
-        if *first {
-            *first = false;
-        } else {
-            let _ = writeln!(stdout);
-        }
-
-        if cfg.color {
-            let _ = writeln!(
-                stdout,
-                "{}{}{}",
-                cfg.file_path_style.prefix(),
-                path.to_string_lossy(),
-                cfg.file_path_style.suffix()
-            );
-        } else {
-            let _ = writeln!(stdout, "{}", path.to_string_lossy());
+    if let (Some(replacement), false) = (&cfg.replace, cfg.preview) {
+        replace::replace_matches(stdout, cfg, path, contents, &matches, replacement);
+    } else if cfg.files_with_matches || cfg.files_without_matches {
+        if matches.is_empty() == cfg.files_without_matches {
+            report::report_path(stdout, cfg, path);
         }
-        *header_printed = true;
-    }
-
-    // Print file path for the match (if not grouping)
-    if !cfg.group {
-        if cfg.color {
-            let _ = write!(
-                stdout,
-                "{}{}{}:",
-                cfg.file_path_style.prefix(),
-                path.to_string_lossy(),
-                cfg.file_path_style.suffix()
-            );
-        } else {
-            let _ = write!(stdout, "{}:", path.to_string_lossy());
+    } else if cfg.count_per_line {
+        report::report_line_counts(stdout, cfg, path, &matches);
+    } else if cfg.count {
+        if !matches.is_empty() || cfg.count_zero {
+            report::report_count(stdout, cfg, path, matches.len());
         }
-    }
-
-    // Print line number
-    if cfg.color {
-        let _ = write!(
-            stdout,
-            "{}{}{}:",
-            cfg.line_num_style.prefix(),
-            line + 1,
-            cfg.line_num_style.suffix()
-        );
     } else {
-        let _ = write!(stdout, "{}:", line + 1);
-    }
-
-    // Print column number (if enabled)
-    if cfg.column {
-        let _ = write!(stdout, "{}:", column + 1);
-    }
-
-    // Print line
-    let line = match lines.get(line) {
-        Some(ok) => ok,
-        None => {
-            eprintln!(
-                "Unable to get line {} in {}",
-                pos.row,
-                path.to_string_lossy()
-            );
-            return;
-        }
-    };
-
-    let before_match = &line[0..column_byte];
-    let match_ = &line[column_byte..column_byte + cfg.pattern.len()];
-    let after_match = &line[column_byte + cfg.pattern.len()..];
-    let _ = write!(stdout, "{}", before_match);
-    if cfg.color {
-        let _ = write!(
+        report::report_matches(
             stdout,
-            "{}{}{}",
-            cfg.match_style.prefix(),
-            match_,
-            cfg.match_style.suffix()
+            cfg,
+            path,
+            contents,
+            lines,
+            capture_names,
+            &matches,
+            first,
         );
-    } else {
-        let _ = write!(stdout, "{}", match_);
     }
-    let _ = writeln!(stdout, "{}", after_match);
-}
-
-#[test]
-fn test_word_bounds() {
-    assert!(check_word_bounds("test", 0, 4));
-    assert!(!check_word_bounds("test", 0, 3));
-    assert!(!check_word_bounds("test", 1, 4));
-    assert!(!check_word_bounds("test", 1, 3));
-    assert!(!check_word_bounds("test", 1, 2));
-    assert!(!check_word_bounds("test", 2, 3));
-    assert!(!check_word_bounds("test", 2, 2));
-
-    assert!(check_word_bounds("a b c", 2, 3));
-    assert!(!check_word_bounds("a b c", 2, 4));
-    assert!(check_word_bounds("a b c", 2, 5));
-}
-
-#[test]
-fn test_match_token() {
-    assert_eq!(match_token("test", "test", false, false, false), vec![0]);
-    assert_eq!(match_token("test", "test", true, false, false), vec![0]);
-    assert_eq!(match_token("test", "Test", true, true, true), vec![]);
-    assert_eq!(match_token("Test", "Test", true, true, true), vec![0]);
-
-    // Whole word
-    assert_eq!(
-        match_token("just testing", "test", false, false, false),
-        vec![5]
-    );
-    assert_eq!(
-        match_token("just testing", "test", false, true, false),
-        vec![]
-    );
 
-    // Multiple occurrences in single token
-    assert_eq!(
-        match_token("tey te tey", "te", false, false, false),
-        vec![0, 4, 7]
-    );
-    assert_eq!(match_token("tey te tey", "te", false, true, false), vec![4]);
-    assert_eq!(match_token("tey Te tey", "Te", false, false, true), vec![4]);
+    matches.len()
 }
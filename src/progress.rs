@@ -0,0 +1,122 @@
+//! Printing number of searched files and the current directory to stderr while searching
+//! (`--progress`)
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How often to update the progress line
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Maximum number of characters in the progress line. Longer lines wrap, and can't be cleared by
+/// moving the cursor to the beginning of the line.
+const MAX_WIDTH: usize = 80;
+
+/// Move the cursor to the beginning of the line and clear the line
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// Number of searched files and the directory being walked or searched. Updated by the search
+/// threads, printed by the thread started with `start`.
+#[derive(Debug, Default)]
+pub(crate) struct Progress {
+    files: AtomicUsize,
+    dir: Mutex<PathBuf>,
+    // Whether the progress line is currently printed. Locked while printing the line, and while
+    // printing the output of a file (see `suspend`).
+    shown: Mutex<bool>,
+}
+
+impl Progress {
+    /// Set the directory being walked or searched
+    pub(crate) fn set_dir(&self, dir: &Path) {
+        let mut current_dir = self.dir.lock().unwrap();
+        if current_dir.as_path() != dir {
+            dir.clone_into(&mut current_dir);
+        }
+    }
+
+    /// Count a searched file
+    pub(crate) fn file_searched(&self) {
+        self.files.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Clear the progress line and run `f`, without printing the progress line while `f` runs.
+    /// Used to print the output of files, which should be flushed in `f`.
+    pub(crate) fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        let mut shown = self.shown.lock().unwrap();
+        if *shown {
+            eprint!("{}", CLEAR_LINE);
+            *shown = false;
+        }
+        f()
+    }
+
+    fn line(&self) -> String {
+        let files = self.files.load(Ordering::Relaxed);
+        let dir = self.dir.lock().unwrap().to_string_lossy().into_owned();
+        progress_line(files, &dir)
+    }
+}
+
+/// Prints the progress line in a thread until dropped. The line is cleared when dropped.
+pub(crate) struct ProgressThread {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+/// Start printing the progress line every `UPDATE_INTERVAL`
+pub(crate) fn start(progress: Arc<Progress>) -> ProgressThread {
+    let (stop, stopped) = mpsc::channel::<()>();
+    let thread = thread::spawn(move || {
+        while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(UPDATE_INTERVAL) {
+            let line = progress.line();
+            let mut shown = progress.shown.lock().unwrap();
+            let mut stderr = io::stderr().lock();
+            let _ = write!(stderr, "{}{}", CLEAR_LINE, line);
+            let _ = stderr.flush();
+            *shown = true;
+        }
+        progress.suspend(|| {});
+    });
+    ProgressThread {
+        stop: Some(stop),
+        thread: Some(thread),
+    }
+}
+
+impl Drop for ProgressThread {
+    fn drop(&mut self) {
+        // Dropping the sender stops the thread
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Progress line with number of searched `files` and the current `dir`. Beginning of `dir` is
+/// elided to keep the line within `MAX_WIDTH` characters.
+fn progress_line(files: usize, dir: &str) -> String {
+    let prefix = format!("{} files searched ", files);
+    let width = MAX_WIDTH.saturating_sub(prefix.chars().count());
+    let dir_len = dir.chars().count();
+    if dir_len <= width {
+        return format!("{}{}", prefix, dir);
+    }
+    let skip = dir_len - width.saturating_sub(1);
+    let dir_end: String = dir.chars().skip(skip).collect();
+    format!("{}…{}", prefix, dir_end)
+}
+
+#[test]
+fn test_progress_line() {
+    assert_eq!(progress_line(0, "src"), "0 files searched src");
+
+    let dir = format!("a/{}", "b".repeat(100));
+    let line = progress_line(12, &dir);
+    assert_eq!(line.chars().count(), MAX_WIDTH);
+    assert!(line.starts_with("12 files searched …b"));
+}
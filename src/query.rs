@@ -0,0 +1,712 @@
+//! Searching tree-sitter queries (`--qs`, `--qn`, `--query-file`)
+
+use std::cmp::{max, min};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use tree_sitter::{Language, Node, QueryCursor, QueryError, QueryErrorKind};
+
+use crate::search::{self, Match};
+use crate::{replace, report, Cfg, Stats};
+
+/// Name of the capture we add to each pattern of a query to capture the whole node matched by the
+/// pattern
+const NODE_CAPTURE_NAME: &str = "__sg_node";
+
+/// A compiled query
+pub(crate) struct Query {
+    query: tree_sitter::Query,
+    /// Index of `NODE_CAPTURE_NAME` capture
+    node_capture_idx: u32,
+    /// Capture indices and expected texts (`--capture`). When not empty, only matches with at
+    /// least one of the captures having the expected text are reported. Texts are lowercased
+    /// when not `case_sensitive`.
+    capture_texts: Vec<(u32, String)>,
+    /// Whether to compare capture texts case sensitively
+    case_sensitive: bool,
+    /// With `--replace`, the replacement of the matched nodes
+    replacement: Option<Vec<ReplacementPart<u32>>>,
+}
+
+/// A part of the replacement of the nodes matched by a query. Captures are referred to by their
+/// names when parsing the replacement, and by their indices in the compiled query.
+#[derive(Debug, PartialEq)]
+enum ReplacementPart<Capture> {
+    Text(String),
+    /// Text of the capture, `@name` in the replacement
+    Capture(Capture),
+}
+
+/// A match of a query in a file
+#[derive(Debug)]
+pub(crate) struct QueryMatch {
+    /// 0-based line numbers of the node matched by the query
+    pub(crate) lines: Range<usize>,
+    /// 0-based line numbers spanning the captures. First line of the node when the query doesn't
+    /// have captures.
+    pub(crate) capture_lines: Range<usize>,
+    /// Captures of the query in the node, in source order
+    pub(crate) captures: Vec<Match>,
+    /// Empty match at the start of the matched node, reported as the match when the query doesn't
+    /// have captures
+    pub(crate) node_start: Match,
+    /// Byte range of the matched node
+    node_range: Range<usize>,
+    /// With `--replace`, indices and byte ranges of the captures, to generate the replacement
+    capture_ranges: Vec<(u32, Range<usize>)>,
+}
+
+impl Query {
+    /// Compile query `source` for `language`. `source_name` is used in error messages.
+    /// `capture_texts` are the capture names and expected texts, as passed to `--capture`,
+    /// compared with the captured texts case sensitively if `case_sensitive`. `replacement` is
+    /// the replacement of the matched nodes, as passed to `--replace`.
+    pub(crate) fn new(
+        language: Language,
+        source: &str,
+        source_name: &str,
+        capture_texts: &[(String, String)],
+        case_sensitive: bool,
+        replacement: Option<&str>,
+    ) -> Result<Query, String> {
+        // Compile the query as given first, to report errors in the user's query
+        let query = tree_sitter::Query::new(language, source)
+            .map_err(|err| query_error_message(source_name, &err))?;
+
+        // Capture the node matched by each pattern. Captures can be added after any pattern, we
+        // add it to the end of the pattern's top-level node.
+        let mut node_query_source = String::with_capacity(source.len());
+        let mut copied = 0;
+        for pattern_idx in 0..query.pattern_count() {
+            let pattern_start = query.start_byte_for_pattern(pattern_idx);
+            let pattern_end = match pattern_node_end(&source[pattern_start..]) {
+                Some(len) => pattern_start + len,
+                None => return Err(format!("Invalid query in {}", source_name)),
+            };
+            node_query_source.push_str(&source[copied..pattern_end]);
+            node_query_source.push_str(" @");
+            node_query_source.push_str(NODE_CAPTURE_NAME);
+            copied = pattern_end;
+        }
+        node_query_source.push_str(&source[copied..]);
+
+        let query = tree_sitter::Query::new(language, &node_query_source)
+            .map_err(|err| query_error_message(source_name, &err))?;
+        let node_capture_idx = query.capture_index_for_name(NODE_CAPTURE_NAME).unwrap();
+
+        let mut capture_idx_texts = Vec::with_capacity(capture_texts.len());
+        for (name, text) in capture_texts {
+            match query.capture_index_for_name(name) {
+                Some(idx) if idx != node_capture_idx => {
+                    let text = if case_sensitive {
+                        text.clone()
+                    } else {
+                        search::lowercase(text)
+                    };
+                    capture_idx_texts.push((idx, text));
+                }
+                _ => {
+                    return Err(format!(
+                        "Query in {} does not have a capture named {}",
+                        source_name, name
+                    ))
+                }
+            }
+        }
+
+        let replacement = match replacement {
+            None => None,
+            Some(replacement) => {
+                let mut parts = vec![];
+                for part in parse_replacement(replacement) {
+                    parts.push(match part {
+                        ReplacementPart::Text(text) => ReplacementPart::Text(text),
+                        ReplacementPart::Capture(name) => match query.capture_index_for_name(&name)
+                        {
+                            Some(idx) if idx != node_capture_idx => ReplacementPart::Capture(idx),
+                            _ => {
+                                return Err(format!(
+                                    "Query in {} does not have a capture named {}, referenced \
+                                    in the replacement",
+                                    source_name, name
+                                ))
+                            }
+                        },
+                    });
+                }
+                Some(parts)
+            }
+        };
+
+        Ok(Query {
+            query,
+            node_capture_idx,
+            capture_texts: capture_idx_texts,
+            case_sensitive,
+            replacement,
+        })
+    }
+
+    /// With `--replace`, the replacement of a node with the captures `capture_ranges` in
+    /// `contents`. A capture that
+    /// matched multiple nodes (e.g. `(_)* @args`) is replaced with the text from the first node to
+    /// the last one. Captures that didn't match anything (e.g. `(_)? @arg`) are removed.
+    fn replacement_text(&self, capture_ranges: &[(u32, Range<usize>)], contents: &str) -> String {
+        let mut text = String::new();
+        for part in self.replacement.iter().flatten() {
+            match part {
+                ReplacementPart::Text(part_text) => text.push_str(part_text),
+                ReplacementPart::Capture(idx) => {
+                    let ranges = capture_ranges
+                        .iter()
+                        .filter(|(capture_idx, _)| capture_idx == idx);
+                    let start = ranges.clone().map(|(_, range)| range.start).min();
+                    let end = ranges.map(|(_, range)| range.end).max();
+                    if let (Some(start), Some(end)) = (start, end) {
+                        text.push_str(&contents[start..end]);
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    /// Whether the text of the captured `node` in `contents` is `text`, as given to `--capture`
+    fn capture_text_matches(&self, node: Node, contents: &str, text: &str) -> bool {
+        let node_text = &contents[node.byte_range()];
+        if self.case_sensitive {
+            node_text == text
+        } else {
+            search::lowercase(node_text) == text
+        }
+    }
+}
+
+/// Split the replacement of the nodes matched by a query into text and references to captures.
+/// `@name` refers to the capture `name`, where `name` is letters, digits, and `_`. `@@` is `@`.
+fn parse_replacement(replacement: &str) -> Vec<ReplacementPart<String>> {
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut chars = replacement.char_indices().peekable();
+    while let Some((idx, char)) = chars.next() {
+        if char != '@' {
+            text.push(char);
+            continue;
+        }
+        if let Some((_, '@')) = chars.peek() {
+            chars.next();
+            text.push('@');
+            continue;
+        }
+        let name_start = idx + 1;
+        let mut name_end = name_start;
+        while let Some((idx, char)) = chars.peek() {
+            if !(char.is_alphanumeric() || *char == '_') {
+                break;
+            }
+            name_end = idx + char.len_utf8();
+            chars.next();
+        }
+        if name_end == name_start {
+            text.push('@');
+            continue;
+        }
+        if !text.is_empty() {
+            parts.push(ReplacementPart::Text(std::mem::take(&mut text)));
+        }
+        parts.push(ReplacementPart::Capture(
+            replacement[name_start..name_end].to_owned(),
+        ));
+    }
+    if !text.is_empty() {
+        parts.push(ReplacementPart::Text(text));
+    }
+    parts
+}
+
+/// Generate an error message for an invalid query in `source_name`, with 1-based line and column
+/// of the error
+fn query_error_message(source_name: &str, err: &QueryError) -> String {
+    let what = match err.kind {
+        QueryErrorKind::Field => "invalid field name ",
+        QueryErrorKind::NodeType => "invalid node type ",
+        QueryErrorKind::Capture => "invalid capture name ",
+        QueryErrorKind::Predicate => "invalid predicate: ",
+        QueryErrorKind::Structure => "impossible pattern:\n",
+        QueryErrorKind::Syntax => "invalid syntax:\n",
+        QueryErrorKind::Language => "",
+    };
+    format!(
+        "Invalid query in {}:{}:{}: {}{}",
+        source_name,
+        err.row + 1,
+        err.column + 1,
+        what,
+        err.message
+    )
+}
+
+/// Returns the length of the top-level node (S-expression, alternation, string, or wildcard) of
+/// the query pattern at the beginning of `pattern`
+fn pattern_node_end(pattern: &str) -> Option<usize> {
+    let bytes = pattern.as_bytes();
+    let mut idx = 0;
+    let mut depth = 0;
+
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b';' => {
+                // Comment, skip to the end of the line
+                while idx < bytes.len() && bytes[idx] != b'\n' {
+                    idx += 1;
+                }
+                continue;
+            }
+            b'"' => {
+                idx += 1;
+                while idx < bytes.len() && bytes[idx] != b'"' {
+                    if bytes[idx] == b'\\' {
+                        idx += 1;
+                    }
+                    idx += 1;
+                }
+                if depth == 0 {
+                    return Some(min(idx + 1, bytes.len()));
+                }
+            }
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx + 1);
+                }
+            }
+            byte if depth == 0 && !byte.is_ascii_whitespace() => {
+                // Wildcard or a node name without parens
+                while idx < bytes.len()
+                    && !bytes[idx].is_ascii_whitespace()
+                    && !b"()[]@;\"".contains(&bytes[idx])
+                {
+                    idx += 1;
+                }
+                return Some(idx);
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+
+    None
+}
+
+/// Queries that come with sg: language name (as in the user's query directory), query name, and
+/// query source
+static BUILTIN_QUERIES: &[(&str, &str, &str)] = &[
+    (
+        "rust",
+        "fn-defs",
+        include_str!("../queries/rust/fn-defs.scm"),
+    ),
+    (
+        "rust",
+        "panic-calls",
+        include_str!("../queries/rust/panic-calls.scm"),
+    ),
+    (
+        "rust",
+        "todo-comments",
+        include_str!("../queries/rust/todo-comments.scm"),
+    ),
+    (
+        "rust",
+        "unsafe-blocks",
+        include_str!("../queries/rust/unsafe-blocks.scm"),
+    ),
+];
+
+/// Source of the built-in query named `name` for language `lang_name`
+pub(crate) fn builtin_query(lang_name: &str, name: &str) -> Option<&'static str> {
+    BUILTIN_QUERIES
+        .iter()
+        .find(|(query_lang, query_name, _)| *query_lang == lang_name && *query_name == name)
+        .map(|(_, _, source)| *source)
+}
+
+/// The user's query directory, with a subdirectory for each language. `None` when neither
+/// `XDG_CONFIG_HOME` nor `HOME` is set.
+fn user_query_dir() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("sg").join("queries"))
+}
+
+/// Load the query named `name` for language `lang_name` from the user's query directory, or from
+/// the built-in queries when the user doesn't have a query with the name. Returns where the query
+/// is loaded from, for error messages, and the query source.
+pub(crate) fn load_named_query(lang_name: &str, name: &str) -> Result<(String, String), String> {
+    let builtin = || {
+        builtin_query(lang_name, name)
+            .map(|source| (format!("built-in query {}", name), source.to_owned()))
+    };
+
+    let path = match user_query_dir() {
+        Some(dir) => dir.join(lang_name).join(format!("{}.scm", name)),
+        None => {
+            return builtin().ok_or_else(|| {
+                format!(
+                    "Unable to find query {}: neither XDG_CONFIG_HOME nor HOME is set",
+                    name
+                )
+            })
+        }
+    };
+
+    match std::fs::read_to_string(&path) {
+        Ok(source) => Ok((path.to_string_lossy().into_owned(), source)),
+        Err(err) => match builtin() {
+            Some(builtin) if err.kind() == std::io::ErrorKind::NotFound => Ok(builtin),
+            _ => Err(format!(
+                "Unable to read query {} in {}: {}",
+                name,
+                path.to_string_lossy(),
+                err
+            )),
+        },
+    }
+}
+
+/// Description of a query: the comment on the first line of `source`
+fn query_description(source: &str) -> &str {
+    source
+        .lines()
+        .next()
+        .and_then(|line| line.trim_start().strip_prefix(';'))
+        .map_or("", |comment| comment.trim_start_matches(';').trim())
+}
+
+/// Print names and descriptions of the queries that can be searched with `--qn` for languages
+/// `lang_names`, or of all languages when `lang_names` is empty. Unless there's only one language,
+/// queries of each language are printed after the language name.
+pub(crate) fn report_queries<W: Write>(stdout: &mut W, lang_names: &[&str]) {
+    // Maps language names to query names and descriptions. User queries override built-in
+    // queries with the same name.
+    let mut queries: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+
+    for (lang_name, name, source) in BUILTIN_QUERIES {
+        if lang_names.is_empty() || lang_names.contains(lang_name) {
+            queries
+                .entry(lang_name.to_string())
+                .or_default()
+                .insert(name.to_string(), query_description(source).to_owned());
+        }
+    }
+
+    if let Some(dir) = user_query_dir() {
+        let user_lang_names: Vec<String> = if lang_names.is_empty() {
+            std::fs::read_dir(&dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        } else {
+            lang_names.iter().map(|name| name.to_string()).collect()
+        };
+
+        for lang_name in user_lang_names {
+            let lang_queries = queries.entry(lang_name.clone()).or_default();
+            for entry in std::fs::read_dir(dir.join(&lang_name))
+                .into_iter()
+                .flatten()
+                .flatten()
+            {
+                let path = entry.path();
+                if path.extension() != Some(OsStr::new("scm")) {
+                    continue;
+                }
+                let name = match path.file_stem() {
+                    Some(name) => name.to_string_lossy().into_owned(),
+                    None => continue,
+                };
+                let description = match std::fs::read_to_string(&path) {
+                    Ok(source) => query_description(&source).to_owned(),
+                    Err(err) => {
+                        eprintln!("Unable to read {}: {}", path.to_string_lossy(), err);
+                        continue;
+                    }
+                };
+                lang_queries.insert(name, description);
+            }
+        }
+    }
+
+    let print_lang_names = lang_names.len() != 1;
+    let mut first = true;
+    for (lang_name, lang_queries) in &queries {
+        if lang_queries.is_empty() {
+            continue;
+        }
+        if print_lang_names {
+            if !first {
+                let _ = writeln!(stdout);
+            }
+            let _ = writeln!(stdout, "{}", lang_name);
+        }
+        first = false;
+        let width = lang_queries
+            .keys()
+            .map(|name| name.len())
+            .max()
+            .unwrap_or(0);
+        for (name, description) in lang_queries {
+            if description.is_empty() {
+                let _ = writeln!(stdout, "{}", name);
+            } else {
+                let _ = writeln!(stdout, "{:width$}  {}", name, description, width = width);
+            }
+        }
+    }
+}
+
+/// Search `query` in the file at `path` with contents `contents` and syntax tree `root`, and
+/// report at most `max_count` matches. Returns the number of reported matches.
+pub(crate) fn search_query<W: Write>(
+    stdout: &mut W,
+    path: &Path,
+    cfg: &Cfg,
+    stats: &Stats,
+    query: &Query,
+    contents: &str,
+    root: Node,
+    max_count: Option<usize>,
+    first: &mut bool,
+) -> usize {
+    let line_starts: Vec<usize> = search::line_starts(contents);
+
+    // Matches, indexed by the byte range of the matched node. Patterns can match the same node
+    // multiple times with different captures, these are reported as one match.
+    let mut node_matches: BTreeMap<(usize, usize), QueryMatch> = BTreeMap::new();
+
+    let mut cursor = QueryCursor::new();
+    for match_ in cursor.matches(&query.query, root, contents.as_bytes()) {
+        let node = match match_
+            .captures
+            .iter()
+            .find(|capture| capture.index == query.node_capture_idx)
+        {
+            Some(capture) => capture.node,
+            None => continue,
+        };
+
+        // With `--capture`, skip the match if none of the captures have the expected text
+        if !query.capture_texts.is_empty()
+            && !match_.captures.iter().any(|capture| {
+                query.capture_texts.iter().any(|(idx, text)| {
+                    capture.index == *idx
+                        && query.capture_text_matches(capture.node, contents, text)
+                })
+            })
+        {
+            continue;
+        }
+
+        let query_match = node_matches
+            .entry((node.start_byte(), node.end_byte()))
+            .or_insert_with(|| QueryMatch {
+                lines: node.start_position().row..node.end_position().row + 1,
+                capture_lines: node.start_position().row..node.start_position().row + 1,
+                captures: vec![],
+                node_start: Match::new(
+                    &node,
+                    &contents[node.byte_range()],
+                    contents,
+                    &line_starts,
+                    0,
+                    0,
+                    cfg.tab_width,
+                ),
+                node_range: node.byte_range(),
+                capture_ranges: vec![],
+            });
+
+        for capture in match_.captures {
+            if capture.index == query.node_capture_idx {
+                continue;
+            }
+            let capture_lines =
+                capture.node.start_position().row..capture.node.end_position().row + 1;
+            if query_match.captures.is_empty() {
+                query_match.capture_lines = capture_lines;
+            } else {
+                query_match.capture_lines.start =
+                    min(query_match.capture_lines.start, capture_lines.start);
+                query_match.capture_lines.end =
+                    max(query_match.capture_lines.end, capture_lines.end);
+            }
+            if query.replacement.is_some() {
+                query_match
+                    .capture_ranges
+                    .push((capture.index, capture.node.byte_range()));
+            }
+            let capture_str = &contents[capture.node.byte_range()];
+            query_match.captures.push(Match {
+                capture: Some(capture.index),
+                ..Match::new(
+                    &capture.node,
+                    capture_str,
+                    contents,
+                    &line_starts,
+                    0,
+                    capture_str.len(),
+                    cfg.tab_width,
+                )
+            });
+        }
+    }
+
+    let mut query_matches: Vec<QueryMatch> = node_matches.into_values().collect();
+
+    // Lines of the file, to report the matches. Only split the file when there is something to
+    // report.
+    let lines: Vec<&str> = if query_matches.is_empty() {
+        vec![]
+    } else {
+        contents.lines().collect()
+    };
+
+    // Nodes at the end of the file can start after the last line (e.g. the root node of a file
+    // with only empty lines), these don't have any lines to report
+    query_matches.retain(|query_match| {
+        let in_file = query_match.capture_lines.start < lines.len();
+        if !in_file {
+            eprintln!(
+                "Skipping match at line {} in {}: line does not exist",
+                query_match.capture_lines.start + 1,
+                path.to_string_lossy()
+            );
+        }
+        in_file
+    });
+
+    if cfg.each_capture {
+        let mut matches: Vec<Match> = query_matches
+            .into_iter()
+            .flat_map(|query_match| query_match.captures)
+            .collect();
+        matches.sort_by_key(|match_| (match_.byte_offset, match_.len));
+        matches.dedup_by_key(|match_| match_.byte_range());
+        return crate::report_file_matches(
+            stdout,
+            path,
+            cfg,
+            stats,
+            contents,
+            &lines,
+            query.query.capture_names(),
+            matches,
+            max_count,
+            first,
+        );
+    }
+
+    if let Some(max_count) = max_count {
+        query_matches.truncate(max_count);
+    }
+    for query_match in &mut query_matches {
+        query_match
+            .captures
+            .sort_by_key(|capture| (capture.byte_offset, capture.len));
+        query_match
+            .captures
+            .dedup_by_key(|capture| capture.byte_range());
+    }
+
+    if query.replacement.is_some() {
+        // Matched nodes can be nested, only replace the outermost ones
+        let mut edits: Vec<(Range<usize>, String)> = Vec::with_capacity(query_matches.len());
+        for query_match in query_matches {
+            let range = query_match.node_range;
+            let text = query.replacement_text(&query_match.capture_ranges, contents);
+            match edits.last_mut() {
+                Some((last_range, _)) if range.start < last_range.end => {
+                    if range.end >= last_range.end {
+                        *edits.last_mut().unwrap() = (range, text);
+                    }
+                }
+                _ => edits.push((range, text)),
+            }
+        }
+        Stats::add(&stats.matches, edits.len());
+        replace::replace_ranges(stdout, cfg, path, contents, &edits);
+        return edits.len();
+    }
+
+    Stats::add(&stats.matches, query_matches.len());
+    report::report_query_matches(stdout, cfg, path, &lines, &query_matches, first);
+
+    query_matches.len()
+}
+
+#[test]
+fn test_pattern_node_end() {
+    assert_eq!(pattern_node_end("(a) (b)"), Some(3));
+    assert_eq!(pattern_node_end("(a (b) @x) @y"), Some(10));
+    assert_eq!(pattern_node_end("; (comment)\n(a)"), Some(15));
+    assert_eq!(pattern_node_end("[(a) (b)] (c)"), Some(9));
+    assert_eq!(pattern_node_end("\"fn\" @x"), Some(4));
+    assert_eq!(pattern_node_end("(a \")\") (b)"), Some(7));
+    assert_eq!(pattern_node_end("_ @x"), Some(1));
+    assert_eq!(pattern_node_end("(a"), None);
+}
+
+#[test]
+fn test_query_error_message() {
+    let language = unsafe { crate::tree_sitter_rust() };
+
+    let err = Query::new(
+        language,
+        "(function_item)\n  (foo)",
+        "--qs",
+        &[],
+        true,
+        None,
+    )
+    .err()
+    .unwrap();
+    assert_eq!(err, "Invalid query in --qs:2:4: invalid node type foo");
+
+    let err = Query::new(language, "(function_item name:", "--qs", &[], true, None)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err,
+        "Invalid query in --qs:1:21: invalid syntax:\n\
+         (function_item name:\n                    ^"
+    );
+}
+
+#[test]
+fn test_parse_replacement() {
+    use ReplacementPart::*;
+
+    assert_eq!(
+        parse_replacement("@f(@x_1, 2)"),
+        vec![
+            Capture("f".to_owned()),
+            Text("(".to_owned()),
+            Capture("x_1".to_owned()),
+            Text(", 2)".to_owned()),
+        ]
+    );
+    assert_eq!(
+        parse_replacement("a@@b @ @"),
+        vec![Text("a@b @ @".to_owned())]
+    );
+    assert_eq!(parse_replacement(""), vec![]);
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::encoding::Encoding;
+use crate::search::Match;
+use crate::{Cfg, STDIN_LABEL};
+
+/// Replace `matches` in `contents` of the file at `path` with `replacement`, write the new
+/// contents back to the file. With `cfg.dry_run`, print a unified diff of the changes instead.
+///
+/// Contents of stdin cannot be written back, so for stdin the new contents are printed to
+/// `stdout`.
+pub(crate) fn replace_matches<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    contents: &str,
+    matches: &[Match],
+    replacement: &str,
+) {
+    if matches.is_empty() {
+        return;
+    }
+
+    let new_contents = apply_replacement(contents, matches, replacement);
+    write_new_contents(stdout, cfg, path, contents, &new_contents);
+}
+
+/// Replace byte ranges in `contents` of the file at `path` with the texts in `edits`, as
+/// `replace_matches`. `edits` should be sorted and should not overlap.
+pub(crate) fn replace_ranges<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    contents: &str,
+    edits: &[(Range<usize>, String)],
+) {
+    if edits.is_empty() {
+        return;
+    }
+
+    let mut new_contents = String::with_capacity(contents.len());
+    let mut copied_byte = 0;
+
+    for (range, text) in edits {
+        new_contents.push_str(&contents[copied_byte..range.start]);
+        new_contents.push_str(text);
+        copied_byte = range.end;
+    }
+
+    new_contents.push_str(&contents[copied_byte..]);
+    write_new_contents(stdout, cfg, path, contents, &new_contents);
+}
+
+/// Write `new_contents` of the file at `path` with the old `contents`, in the encoding of the
+/// file. With `cfg.dry_run`, print a unified diff of the changes instead. For stdin, print the
+/// new contents.
+fn write_new_contents<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    contents: &str,
+    new_contents: &str,
+) {
+    if cfg.dry_run {
+        let path_str = path.to_string_lossy();
+        let diff = similar::TextDiff::from_lines(contents, new_contents);
+        let _ = write!(
+            stdout,
+            "{}",
+            diff.unified_diff().header(&path_str, &path_str)
+        );
+    } else if path == Path::new(STDIN_LABEL) {
+        let _ = write!(stdout, "{}", new_contents);
+    } else if let Err(err) = file_encoding(cfg, path)
+        .encode(new_contents)
+        .and_then(|bytes| fs::write(path, bytes))
+    {
+        eprintln!("Unable to write {}: {}", path.to_string_lossy(), err);
+    }
+}
+
+/// Encoding of the file at `path`, to write the file back in the same encoding
+fn file_encoding(cfg: &Cfg, path: &Path) -> Encoding {
+    let mut bom = vec![];
+    let _ = fs::File::open(path).and_then(|file| file.take(3).read_to_end(&mut bom));
+    Encoding::detect(&bom, cfg.encoding)
+}
+
+/// Replace `matches` in `contents` with `replacement`. `matches` should be sorted and should not
+/// overlap.
+fn apply_replacement(contents: &str, matches: &[Match], replacement: &str) -> String {
+    let mut new_contents = String::with_capacity(contents.len());
+    let mut copied_byte = 0;
+
+    for match_ in matches {
+        let range = match_.byte_range();
+        new_contents.push_str(&contents[copied_byte..range.start]);
+        new_contents.push_str(replacement);
+        copied_byte = range.end;
+    }
+
+    new_contents.push_str(&contents[copied_byte..]);
+    new_contents
+}
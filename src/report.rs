@@ -0,0 +1,635 @@
+use std::cmp::{max, min};
+use std::io::Write;
+use std::path::Path;
+
+use crate::query::QueryMatch;
+use crate::search::{self, Match, Patterns};
+use crate::Cfg;
+
+/// Reporting state of the file being searched
+#[derive(Debug, Default)]
+struct FileReportState {
+    /// Did we print the file header? Header is the file name with `cfg.heading`, and nothing
+    /// otherwise.
+    header_printed: bool,
+
+    /// 0-based number of the last line printed, as a matched line or context line. Used to avoid
+    /// printing context lines multiple times when context of matches overlap.
+    last_printed_line: Option<usize>,
+
+    /// 0-based line number (exclusive) up to which we should print context lines after the last
+    /// match. We print these lazily, when we see the next match or finish the file, as the next
+    /// match may be in one of these lines.
+    after_context_end: usize,
+}
+
+/// # Arguments
+///
+/// * `stdout`: A `Write` implementation to write the report. This function does not use process
+///   stdout directly, writes to this instead.
+///
+/// * `cfg`: User configuration (derived from defaults and CLI args)
+///
+/// * `path`: Path of the file with the matches. Will be printed directly to `stdout`.
+///
+/// * `contents`: Contents of the file at `path`.
+///
+/// * `lines`: Lines of the file at `path`.
+///
+/// * `capture_names`: Names of the query captures of `matches`, with `--each-capture`. Printed in
+///   `--json` output.
+///
+/// * `matches`: Matches in the file, in source order. Matches on the same line are printed
+///   together, as one line with all matches highlighted.
+///
+/// * `first`: Whether this is the first file with output. Files are separated with empty lines
+///   when grouping (default, without `--nogroup`).
+///
+pub(crate) fn report_matches<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    contents: &str,
+    lines: &[&str],
+    capture_names: &[String],
+    matches: &[Match],
+    first: &mut bool,
+) {
+    if cfg.json {
+        for match_ in matches {
+            let line_str = match get_line(path, lines, match_.line) {
+                Some(line_str) => line_str,
+                None => continue,
+            };
+            print_json_match(
+                stdout,
+                cfg,
+                path,
+                match_,
+                &contents[match_.byte_range()],
+                line_str,
+                symbol_str(contents, match_),
+                match_
+                    .capture
+                    .and_then(|capture| capture_names.get(capture as usize)),
+            );
+        }
+        return;
+    }
+
+    if cfg.vimgrep {
+        for match_ in matches {
+            let line_str = match get_line(path, lines, match_.line) {
+                Some(line_str) => line_str,
+                None => continue,
+            };
+            write_path(stdout, cfg, path, ":");
+            let _ = write!(stdout, "{}:{}:", match_.line + 1, match_.column + 1);
+            if let Some(symbol) = symbol_str(contents, match_) {
+                let _ = write!(stdout, "{}:", symbol);
+            }
+            let _ = writeln!(stdout, "{}", line_str);
+        }
+        return;
+    }
+
+    let mut state = FileReportState::default();
+
+    // Highlight matches spanning multiple lines on each of their lines. When previewing
+    // replacements, the replacement is shown once, on the first line of the match.
+    let matches: Vec<Match> = if cfg.preview {
+        matches.to_vec()
+    } else {
+        matches
+            .iter()
+            .flat_map(|match_| match_.line_segments(contents))
+            .collect()
+    };
+
+    let mut line_matches_start = 0;
+    while line_matches_start < matches.len() {
+        let line = matches[line_matches_start].line;
+        let line_matches_len = matches[line_matches_start..]
+            .iter()
+            .take_while(|match_| match_.line == line)
+            .count();
+        let line_matches = &matches[line_matches_start..line_matches_start + line_matches_len];
+        line_matches_start += line_matches_len;
+
+        report_line(
+            stdout,
+            cfg,
+            path,
+            contents,
+            lines,
+            line_matches,
+            &mut state,
+            first,
+        );
+    }
+
+    // Print remaining context lines after the last match
+    if let Some(last_printed_line) = state.last_printed_line {
+        let end = min(state.after_context_end, lines.len());
+        print_context_lines(
+            stdout,
+            cfg,
+            path,
+            lines,
+            last_printed_line + 1,
+            end,
+            &mut state,
+        );
+    }
+}
+
+/// Print a matched line, with all of the matches in the line highlighted. `line_matches` should
+/// be non-empty, and all matches should be on the same line.
+fn report_line<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    contents: &str,
+    lines: &[&str],
+    line_matches: &[Match],
+    state: &mut FileReportState,
+    first: &mut bool,
+) {
+    let line = line_matches[0].line;
+
+    let line_str = match get_line(path, lines, line) {
+        Some(line_str) => line_str,
+        None => return,
+    };
+
+    print_header(stdout, cfg, path, state, first);
+
+    let (mut before_context_start, context_end) = line_context(cfg, line, line_matches);
+
+    // Print context of the previous match first, then context of this match
+    if let Some(last_printed_line) = state.last_printed_line {
+        let after_context_end = min(state.after_context_end, line);
+        print_context_lines(
+            stdout,
+            cfg,
+            path,
+            lines,
+            last_printed_line + 1,
+            after_context_end,
+            state,
+        );
+    }
+    // After context of the previous match may overlap with before context of this match
+    if let Some(last_printed_line) = state.last_printed_line {
+        before_context_start = max(before_context_start, last_printed_line + 1);
+    }
+    print_context_separator(stdout, cfg, state, before_context_start);
+    print_context_lines(stdout, cfg, path, lines, before_context_start, line, state);
+
+    let symbol = symbol_str(contents, &line_matches[0]);
+    print_matched_line(stdout, cfg, path, line, line_str, line_matches, symbol);
+
+    state.last_printed_line = Some(line);
+    state.after_context_end = context_end;
+}
+
+/// Context lines of the matched 0-based line `line` with matches `line_matches`: first line of the
+/// before context, and the line (exclusive) the after context ends
+fn line_context(cfg: &Cfg, line: usize, line_matches: &[Match]) -> (usize, usize) {
+    let mut start = line.saturating_sub(cfg.before_context);
+    let mut end = line + 1 + cfg.after_context;
+    for match_ in line_matches {
+        if let Some((node_start, node_end)) = match_.context_lines {
+            start = min(start, node_start);
+            end = max(end, node_end);
+        }
+    }
+    (start, end)
+}
+
+/// Name of the definition containing `match_`, with `--context-symbol`
+fn symbol_str<'a>(contents: &'a str, match_: &Match) -> Option<&'a str> {
+    match_.symbol.map(|(start, end)| &contents[start..end])
+}
+
+/// Print 0-based line `line` with contents `line_str` as a matched line, with `line_matches`
+/// highlighted. `line_matches` is empty for query matches without captures. `symbol` is the name
+/// of the definition containing the first match, with `--context-symbol`.
+fn print_matched_line<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    line: usize,
+    line_str: &str,
+    line_matches: &[Match],
+    symbol: Option<&str>,
+) {
+    print_file_path(stdout, cfg, path, ":");
+    print_line_number(stdout, cfg, line, ':');
+
+    // Print column number and byte offset of the first match (if enabled)
+    if let Some(match_) = line_matches.first() {
+        if cfg.column {
+            let _ = write!(stdout, "{}:", match_.column + 1);
+        }
+        if cfg.byte_offset {
+            let _ = write!(stdout, "{}:", match_.byte_offset);
+        }
+    }
+    if let Some(symbol) = symbol {
+        let _ = write!(stdout, "{}:", symbol);
+    }
+
+    // Print the line in alternating plain and highlighted parts
+    let mut printed_byte = 0;
+    for (highlight_begin, highlight_end) in highlight_ranges(cfg, line_str, line_matches) {
+        let _ = write!(stdout, "{}", &line_str[printed_byte..highlight_begin]);
+
+        let highlight_str = &line_str[highlight_begin..highlight_end];
+        match (&cfg.replace, cfg.preview) {
+            (Some(replacement), true) => {
+                print_replacement(stdout, cfg, highlight_str, replacement);
+            }
+            _ => {
+                if cfg.color {
+                    let _ = write!(
+                        stdout,
+                        "{}{}{}",
+                        cfg.match_style.prefix(),
+                        highlight_str,
+                        cfg.match_style.suffix()
+                    );
+                } else {
+                    let _ = write!(stdout, "{}", highlight_str);
+                }
+            }
+        }
+
+        printed_byte = highlight_end;
+    }
+    let _ = writeln!(stdout, "{}", &line_str[printed_byte..]);
+}
+
+/// Byte ranges to highlight in the matched line `line_str` with `line_matches`, sorted and not
+/// overlapping. Matches can overlap (with `--regex`), the part of a match that is not highlighted
+/// yet is highlighted. Only the matched characters of `--fuzzy` matches are highlighted.
+///
+/// With `--replace --preview` there's one range for each match, to print the replacement of each
+/// match. Otherwise adjacent ranges are merged and empty ranges are skipped.
+fn highlight_ranges(cfg: &Cfg, line_str: &str, line_matches: &[Match]) -> Vec<(usize, usize)> {
+    let preview = cfg.replace.is_some() && cfg.preview;
+
+    let mut ranges: Vec<(usize, usize)> = Vec::with_capacity(line_matches.len());
+    let mut highlighted_byte = 0;
+    for match_ in line_matches {
+        let match_begin = min(max(match_.column_byte, highlighted_byte), line_str.len());
+        let match_end = max(
+            min(match_.column_byte + match_.len, line_str.len()),
+            match_begin,
+        );
+        highlighted_byte = match_end;
+
+        if preview {
+            ranges.push((match_begin, match_end));
+            continue;
+        }
+
+        let match_ranges = match (&cfg.patterns, match_.pattern) {
+            (Patterns::Literal(patterns), Some(pattern_idx)) if cfg.fuzzy => {
+                let (pattern, case_sensitive) = &patterns[pattern_idx];
+                search::fuzzy_match_spans(
+                    &line_str[match_begin..match_end],
+                    pattern,
+                    *case_sensitive,
+                )
+                .into_iter()
+                .map(|(span_begin, span_end)| (match_begin + span_begin, match_begin + span_end))
+                .collect()
+            }
+            _ => vec![(match_begin, match_end)],
+        };
+
+        for (range_begin, range_end) in match_ranges {
+            if range_begin == range_end {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some((_, last_end)) if *last_end == range_begin => *last_end = range_end,
+                _ => ranges.push((range_begin, range_end)),
+            }
+        }
+    }
+    ranges
+}
+
+/// Print `match_str` followed by its `replacement`, for `--replace --preview`. Without colors the
+/// texts are marked as `[-match_str-]{+replacement+}`.
+fn print_replacement<W: Write>(stdout: &mut W, cfg: &Cfg, match_str: &str, replacement: &str) {
+    if cfg.color {
+        let _ = write!(
+            stdout,
+            "{}{}{}{}{}{}",
+            cfg.removed_style.prefix(),
+            match_str,
+            cfg.removed_style.suffix(),
+            cfg.added_style.prefix(),
+            replacement,
+            cfg.added_style.suffix()
+        );
+    } else {
+        let _ = write!(stdout, "[-{}-]{{+{}+}}", match_str, replacement);
+    }
+}
+
+/// Report matches of a query in a file. The first line of each matched node is printed for
+/// context, followed by the lines of the captures. Lines of the node that are not printed are
+/// marked with `…`. With `--full-node` all lines of the matched nodes are printed. Lines with
+/// captures are printed as matched lines, with the captures highlighted.
+pub(crate) fn report_query_matches<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    lines: &[&str],
+    query_matches: &[QueryMatch],
+    first: &mut bool,
+) {
+    let mut state = FileReportState::default();
+
+    for query_match in query_matches {
+        print_header(stdout, cfg, path, &mut state, first);
+
+        // Captures are sorted, captures of a line are the next captures starting at the line
+        let mut captures = &query_match.captures[..];
+
+        // Ranges of lines to print
+        let node_start = query_match.lines.start;
+        let mut ranges = Vec::with_capacity(2);
+        if cfg.full_node {
+            ranges.push(query_match.lines.clone());
+        } else {
+            let start = query_match
+                .capture_lines
+                .start
+                .saturating_sub(cfg.before_context);
+            let end = query_match.capture_lines.end + cfg.after_context;
+            if node_start < start {
+                ranges.push(node_start..node_start + 1);
+            }
+            ranges.push(start..end);
+        }
+
+        // End of the last range printed for this match
+        let mut match_printed_end: Option<usize> = None;
+
+        for range in ranges {
+            // Matched nodes can overlap, print each line once
+            let start = match state.last_printed_line {
+                Some(last_printed_line) => max(range.start, last_printed_line + 1),
+                None => range.start,
+            };
+            let end = min(range.end, lines.len());
+            if start >= end {
+                continue;
+            }
+
+            match match_printed_end {
+                Some(printed_end) if start > printed_end => print_elided_lines(stdout),
+                Some(_) => {}
+                None => print_context_separator(stdout, cfg, &state, start),
+            }
+
+            for line in start..end {
+                let skipped = captures
+                    .iter()
+                    .take_while(|capture| capture.line < line)
+                    .count();
+                captures = &captures[skipped..];
+                let line_matches_len = captures
+                    .iter()
+                    .take_while(|capture| capture.line == line)
+                    .count();
+                let line_matches = &captures[..line_matches_len];
+
+                // Without captures the first line of the node is the matched line, with the start
+                // of the node as the match (for `--column` and `--byte-offset`)
+                let line_matches = if query_match.captures.is_empty() && line == node_start {
+                    std::slice::from_ref(&query_match.node_start)
+                } else {
+                    line_matches
+                };
+                let matched = !line_matches.is_empty();
+
+                if !matched {
+                    print_context_lines(stdout, cfg, path, lines, line, line + 1, &mut state);
+                } else {
+                    print_matched_line(stdout, cfg, path, line, lines[line], line_matches, None);
+                    state.last_printed_line = Some(line);
+                }
+            }
+
+            match_printed_end = Some(end);
+        }
+
+        if match_printed_end.is_some_and(|printed_end| printed_end < query_match.lines.end) {
+            print_elided_lines(stdout);
+        }
+    }
+}
+
+/// Mark lines of a matched node that are not printed
+fn print_elided_lines<W: Write>(stdout: &mut W) {
+    let _ = writeln!(stdout, "…");
+}
+
+/// Get 0-based line `line`, report an error if it doesn't exist
+fn get_line<'a>(path: &Path, lines: &[&'a str], line: usize) -> Option<&'a str> {
+    let line_str = lines.get(line).copied();
+    if line_str.is_none() {
+        eprintln!("Unable to get line {} in {}", line, path.to_string_lossy());
+    }
+    line_str
+}
+
+/// Print path of a file, for `--files-with-matches` and `--files-without-matches`
+pub(crate) fn report_path<W: Write>(stdout: &mut W, cfg: &Cfg, path: &Path) {
+    write_path(stdout, cfg, path, "\n");
+}
+
+/// Print number of matches in a file, for `--count`
+pub(crate) fn report_count<W: Write>(stdout: &mut W, cfg: &Cfg, path: &Path, count: usize) {
+    write_path(stdout, cfg, path, ": ");
+    let _ = writeln!(stdout, "{}", count);
+}
+
+/// Print number of matches in each matched line, for `--count-per-line`. `matches` should be
+/// sorted. Matches spanning multiple lines are counted in their first lines.
+pub(crate) fn report_line_counts<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    matches: &[Match],
+) {
+    let mut line_matches_start = 0;
+    while line_matches_start < matches.len() {
+        let line = matches[line_matches_start].line;
+        let line_matches_len = matches[line_matches_start..]
+            .iter()
+            .take_while(|match_| match_.line == line)
+            .count();
+        line_matches_start += line_matches_len;
+
+        write_path(stdout, cfg, path, ":");
+        print_line_number(stdout, cfg, line, ':');
+        let _ = writeln!(stdout, " {}", line_matches_len);
+    }
+}
+
+/// Print 0-based lines in range `start..end` as context lines
+fn print_context_lines<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    lines: &[&str],
+    start: usize,
+    end: usize,
+    state: &mut FileReportState,
+) {
+    for (line, line_str) in lines.iter().enumerate().take(end).skip(start) {
+        print_file_path(stdout, cfg, path, "-");
+        print_line_number(stdout, cfg, line, '-');
+        let _ = writeln!(stdout, "{}", line_str);
+        state.last_printed_line = Some(line);
+    }
+}
+
+/// With context lines, print a separator when 0-based line `next_line` that we are about to print
+/// does not follow the last printed line
+fn print_context_separator<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    state: &FileReportState,
+    next_line: usize,
+) {
+    if cfg.before_context == 0 && cfg.after_context == 0 && cfg.context_node_kinds.is_empty() {
+        return;
+    }
+    if let Some(last_printed_line) = state.last_printed_line {
+        if next_line > last_printed_line + 1 {
+            let _ = writeln!(stdout, "--");
+        }
+    }
+}
+
+/// Print the file header, before the first line printed for the file. With `cfg.group`, the header
+/// is separated from the previous file's output with an empty line. With `cfg.heading`, the
+/// header is the file name.
+fn print_header<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    state: &mut FileReportState,
+    first: &mut bool,
+) {
+    if state.header_printed {
+        return;
+    }
+    state.header_printed = true;
+
+    if *first {
+        *first = false;
+    } else if cfg.group {
+        let _ = writeln!(stdout);
+    }
+
+    if cfg.heading {
+        write_path(stdout, cfg, path, "\n");
+    }
+}
+
+/// Print file path for the match (without `cfg.heading`). `sep` is ":" for matched lines, "-" for
+/// context lines.
+fn print_file_path<W: Write>(stdout: &mut W, cfg: &Cfg, path: &Path, sep: &str) {
+    if !cfg.heading {
+        write_path(stdout, cfg, path, sep);
+    }
+}
+
+/// Print `path` followed by `sep`. With `cfg.null` a NUL byte is printed instead of `sep`, to
+/// allow parsing the output when paths contain newlines or separator characters.
+fn write_path<W: Write>(stdout: &mut W, cfg: &Cfg, path: &Path, sep: &str) {
+    let sep = if cfg.null { "\0" } else { sep };
+    if cfg.color {
+        let _ = write!(
+            stdout,
+            "{}{}{}{}",
+            cfg.file_path_style.prefix(),
+            path.to_string_lossy(),
+            cfg.file_path_style.suffix(),
+            sep
+        );
+    } else {
+        let _ = write!(stdout, "{}{}", path.to_string_lossy(), sep);
+    }
+}
+
+/// Print 0-based `line` as 1-based line number. `sep` is ':' for matched lines, '-' for context
+/// lines.
+fn print_line_number<W: Write>(stdout: &mut W, cfg: &Cfg, line: usize, sep: char) {
+    if !cfg.line_number {
+        return;
+    }
+
+    if cfg.color {
+        let _ = write!(
+            stdout,
+            "{}{}{}{}",
+            cfg.line_num_style.prefix(),
+            line + 1,
+            cfg.line_num_style.suffix(),
+            sep
+        );
+    } else {
+        let _ = write!(stdout, "{}{}", line + 1, sep);
+    }
+}
+
+/// Print a match as a single-line JSON object, or with `--json-pretty` as an indented
+/// multi-line object. Line and column are printed as 1-based, byte offset is the offset of the
+/// match in the file. `node_kind` is the tree-sitter node kind of the match (`null` for lines
+/// printed with `--invert-match`). With `--context-symbol` the name of the definition containing
+/// the match (or `null`) is printed as `symbol`, with `--each-capture` the capture name as
+/// `capture`, and with multiple patterns the 0-based index of the matched pattern as `pattern`.
+fn print_json_match<W: Write>(
+    stdout: &mut W,
+    cfg: &Cfg,
+    path: &Path,
+    match_: &Match,
+    match_text: &str,
+    line_text: &str,
+    symbol: Option<&str>,
+    capture: Option<&String>,
+) {
+    let mut json = serde_json::json!({
+        "path": path.to_string_lossy(),
+        "line": match_.line + 1,
+        "column": match_.column + 1,
+        "byte_offset": match_.byte_offset,
+        "match_text": match_text,
+        "line_text": line_text,
+        "node_kind": match_.node_kind,
+    });
+    if cfg.context_symbol {
+        json["symbol"] = serde_json::json!(symbol);
+    }
+    if let Some(capture) = capture {
+        json["capture"] = serde_json::json!(capture);
+    }
+    if cfg.patterns.len() > 1 {
+        json["pattern"] = serde_json::json!(match_.pattern);
+    }
+    if cfg.json_pretty {
+        let _ = writeln!(stdout, "{:#}", json);
+    } else {
+        let _ = writeln!(stdout, "{}", json);
+    }
+}
@@ -0,0 +1,727 @@
+//! Matching the search pattern against tokens
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+
+use regex::{Regex, RegexBuilder};
+use tree_sitter::Node;
+
+use crate::Cfg;
+
+/// Patterns to search. Matches of any of the patterns are reported.
+pub(crate) enum Patterns {
+    /// Literal patterns, and whether to match them case sensitively. Patterns matched case
+    /// insensitively are lowercased with `lowercase`.
+    Literal(Vec<(String, bool)>),
+    /// Regex patterns, with `--regex`
+    Regex(Vec<RegexPattern>),
+}
+
+impl Patterns {
+    /// Number of patterns
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Patterns::Literal(patterns) => patterns.len(),
+            Patterns::Regex(regexes) => regexes.len(),
+        }
+    }
+}
+
+/// A compiled regex pattern
+pub(crate) struct RegexPattern {
+    /// The pattern as given by the user
+    regex: Regex,
+    /// The pattern anchored at both ends, for matching whole identifiers
+    anchored: Regex,
+}
+
+impl RegexPattern {
+    pub(crate) fn new(pattern: &str, case_sensitive: bool) -> Result<RegexPattern, regex::Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(!case_sensitive)
+            .build()?;
+        let anchored = RegexBuilder::new(&format!("^(?:{})$", pattern))
+            .case_insensitive(!case_sensitive)
+            .build()?;
+        Ok(RegexPattern { regex, anchored })
+    }
+}
+
+/// A match of the searched pattern in a file
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Match {
+    /// 0-based line number of the match
+    pub(crate) line: usize,
+
+    /// 0-based column of the match, in characters. Tabs are counted as `--tab-width` columns.
+    pub(crate) column: usize,
+
+    /// Byte index of the match in the line
+    pub(crate) column_byte: usize,
+
+    /// Length of the match in bytes
+    pub(crate) len: usize,
+
+    /// Byte index of the match in the file
+    pub(crate) byte_offset: usize,
+
+    /// With `--context-symbol`, byte range of the name of the innermost definition containing the
+    /// match in the file
+    pub(crate) symbol: Option<(usize, usize)>,
+
+    /// With `--context-node`, 0-based line range (end exclusive) of the node to print as the
+    /// context of the match
+    pub(crate) context_lines: Option<(usize, usize)>,
+
+    /// Kind of the tree-sitter node with the match. `None` for lines reported with
+    /// `--invert-match`.
+    pub(crate) node_kind: Option<&'static str>,
+
+    /// With `--each-capture`, index of the query capture of the match
+    pub(crate) capture: Option<u32>,
+
+    /// Index of the pattern of the match, in the order the patterns are given. `None` for lines
+    /// reported with `--invert-match` and query matches.
+    pub(crate) pattern: Option<usize>,
+}
+
+impl Match {
+    /// # Arguments
+    ///
+    /// * `node`: tree-sitter node with the match. If you convert this node to string with
+    ///   `node.utf8_text()` (use `token_str`), then the searched term will be in the string.
+    ///
+    /// * `token_str`: `node.utf8_text()`
+    ///
+    /// * `contents`: Contents of the file that `node` is in.
+    ///
+    /// * `line_starts`: Byte indices of line starts in `contents`. See `line_starts`.
+    ///
+    /// * `match_byte_idx`: Byte index (in `token_str`) of the match of the searched term in
+    ///   `token_str`.
+    ///
+    /// * `match_len`: Length of the match in bytes.
+    ///
+    /// * `tab_width`: Number of columns to count for a tab character.
+    pub(crate) fn new(
+        node: &Node,
+        token_str: &str,
+        contents: &str,
+        line_starts: &[usize],
+        match_byte_idx: usize,
+        match_len: usize,
+        tab_width: usize,
+    ) -> Match {
+        let pos = node.start_position();
+
+        let (token_line, mut column_byte) = get_token_line_col(token_str, match_byte_idx);
+
+        // If we didn't skip any lines, `column_byte` need to be added to the beginning of the
+        // token
+        if token_line == 0 {
+            column_byte += node.start_byte() - line_starts[pos.row];
+        }
+
+        let line = pos.row + token_line;
+        let line_start = line_starts[line];
+        let column = get_column(&contents[line_start..line_start + column_byte], tab_width);
+
+        Match {
+            line,
+            column,
+            column_byte,
+            len: match_len,
+            byte_offset: node.start_byte() + match_byte_idx,
+            symbol: None,
+            context_lines: None,
+            node_kind: Some(node.kind()),
+            capture: None,
+            pattern: None,
+        }
+    }
+
+    /// A match of length `len` at byte index `byte_offset` in `contents`. Unlike `new`, the match
+    /// does not need to be in a single token.
+    pub(crate) fn at(
+        contents: &str,
+        line_starts: &[usize],
+        byte_offset: usize,
+        len: usize,
+        tab_width: usize,
+    ) -> Match {
+        let line = match line_starts.binary_search(&byte_offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let line_start = line_starts[line];
+        Match {
+            line,
+            column: get_column(&contents[line_start..byte_offset], tab_width),
+            column_byte: byte_offset - line_start,
+            len,
+            byte_offset,
+            symbol: None,
+            context_lines: None,
+            node_kind: None,
+            capture: None,
+            pattern: None,
+        }
+    }
+
+    /// An empty match at the beginning of the 0-based line `line`. Used to report lines without
+    /// matches with `--invert-match`.
+    pub(crate) fn line_start(line_starts: &[usize], line: usize) -> Match {
+        Match {
+            line,
+            column: 0,
+            column_byte: 0,
+            len: 0,
+            byte_offset: line_starts[line],
+            symbol: None,
+            context_lines: None,
+            node_kind: None,
+            capture: None,
+            pattern: None,
+        }
+    }
+
+    /// 0-based line number of the match
+    pub(crate) fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Byte range of the match in the file
+    pub(crate) fn byte_range(&self) -> std::ops::Range<usize> {
+        self.byte_offset..self.byte_offset + self.len
+    }
+
+    /// Split a match spanning multiple lines into one match per line. `contents` is the contents
+    /// of the file with the match. Parts on lines after the first one start at column 0.
+    pub(crate) fn line_segments(self, contents: &str) -> impl Iterator<Item = Match> + '_ {
+        let mut byte_offset = self.byte_offset;
+        contents[self.byte_range()]
+            .split('\n')
+            .enumerate()
+            .map(move |(line_idx, segment)| {
+                let match_ = if line_idx == 0 {
+                    Match {
+                        len: segment.len(),
+                        ..self
+                    }
+                } else {
+                    Match {
+                        line: self.line + line_idx,
+                        column: 0,
+                        column_byte: 0,
+                        len: segment.len(),
+                        byte_offset,
+                        symbol: self.symbol,
+                        context_lines: self.context_lines,
+                        node_kind: self.node_kind,
+                        capture: self.capture,
+                        pattern: self.pattern,
+                    }
+                };
+                byte_offset += segment.len() + 1;
+                match_
+            })
+    }
+}
+
+/// Returns byte indices of line starts in `contents`. Lines are terminated by "\n", which may be
+/// preceded by "\r". Index `i` of the returned vector is the byte index of 0-based line `i`.
+pub(crate) fn line_starts(contents: &str) -> Vec<usize> {
+    let mut line_starts = vec![0];
+    line_starts.extend(
+        contents
+            .bytes()
+            .enumerate()
+            .filter(|(_, byte)| *byte == b'\n')
+            .map(|(idx, _)| idx + 1),
+    );
+    line_starts
+}
+
+/// Returns indices of the patterns and byte ranges of matches of the patterns in `cfg` in `token`,
+/// in order. `is_id` should be `true` when `token` is an identifier.
+pub(crate) fn find_matches(token: &str, cfg: &Cfg, is_id: bool) -> Vec<(usize, (usize, usize))> {
+    let mut matches: Vec<(usize, (usize, usize))> = match &cfg.patterns {
+        Patterns::Literal(patterns) => patterns
+            .iter()
+            .enumerate()
+            .flat_map(|(pattern_idx, (pattern, case_sensitive))| {
+                let matches = if cfg.fuzzy {
+                    match_token_fuzzy(token, pattern, *case_sensitive)
+                } else {
+                    match_token(token, pattern, is_id, cfg.whole_word, *case_sensitive)
+                };
+                matches.into_iter().map(move |range| (pattern_idx, range))
+            })
+            .collect(),
+        Patterns::Regex(regexes) => regexes
+            .iter()
+            .enumerate()
+            .flat_map(|(pattern_idx, regex)| {
+                match_token_regex(token, regex, is_id, cfg.whole_word)
+                    .into_iter()
+                    .map(move |range| (pattern_idx, range))
+            })
+            .collect(),
+    };
+
+    // Matches of different patterns can overlap. Of the matches starting at the same index report
+    // the longest one (of the first pattern, when multiple patterns match the same text), and skip
+    // the ones overlapping with a previous match.
+    if cfg.patterns.len() > 1 {
+        matches.sort_by_key(|(pattern_idx, (match_begin, match_end))| {
+            (*match_begin, Reverse(*match_end), *pattern_idx)
+        });
+        let mut last_end = None;
+        matches.retain(|(_, (match_begin, match_end))| {
+            if last_end.is_some_and(|last_end| *match_begin < last_end) {
+                return false;
+            }
+            last_end = Some(*match_end);
+            true
+        });
+    }
+
+    matches
+}
+
+/// Lowercase `text` for case insensitive matching. Patterns and tokens should be lowercased with
+/// this function, as it lowercases each character independently (unlike `str::to_lowercase`,
+/// which lowercases final sigma differently), which allows mapping matches in lowercased tokens
+/// back to the original tokens.
+pub(crate) fn lowercase(text: &str) -> String {
+    text.chars().flat_map(char::to_lowercase).collect()
+}
+
+/// Lowercase `token` as `lowercase`. Returns the lowercased token, and for each byte index in the
+/// lowercased token (and the end of the token), the byte index in `token` of the character that
+/// was lowercased to the character at the index. Indices in the middle of the lowercase version
+/// of a character (e.g. 'İ' is lowercased to two characters) are `None`.
+fn lowercase_with_indices(token: &str) -> (String, Vec<Option<usize>>) {
+    let mut lowercase = String::with_capacity(token.len());
+    let mut indices = Vec::with_capacity(token.len() + 1);
+    for (char_idx, char) in token.char_indices() {
+        indices.push(Some(char_idx));
+        lowercase.extend(char.to_lowercase());
+        indices.resize(lowercase.len(), None);
+    }
+    indices.push(Some(token.len()));
+    (lowercase, indices)
+}
+
+/// Whether the match at `match_begin..match_end` in `text` is a whole word: not preceded or
+/// followed by a word character
+fn check_word_bounds(text: &str, match_begin: usize, match_end: usize) -> bool {
+    if let Some(char) = text[..match_begin].chars().next_back() {
+        if is_word_char(char) {
+            return false;
+        }
+    }
+
+    if let Some(char) = text[match_end..].chars().next() {
+        if is_word_char(char) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Word characters are letters, digits, and `_`, as `\w` in regexes
+pub(crate) fn is_word_char(char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+/// Returns byte ranges of matches of `pattern` in `token`. When not `case_sensitive`, `pattern`
+/// should be lowercased with `lowercase`. Empty pattern matches the whole token.
+fn match_token(
+    token: &str,
+    pattern: &str,
+    is_id: bool,
+    whole_word: bool,
+    case_sensitive: bool,
+) -> Vec<(usize, usize)> {
+    #[cfg(debug_assertions)]
+    if !case_sensitive {
+        assert_eq!(pattern, lowercase(pattern));
+    }
+
+    // Empty pattern (`--dump`) matches whole tokens
+    if pattern.is_empty() {
+        return if token.is_empty() {
+            vec![]
+        } else {
+            vec![(0, token.len())]
+        };
+    }
+
+    // Lowercasing ASCII tokens does not change byte indices of the characters. For other tokens
+    // we need to map matches in the lowercased token back to the token.
+    let (token, token_indices): (Cow<'_, str>, Option<Vec<Option<usize>>>) = if case_sensitive {
+        (Cow::Borrowed(token), None)
+    } else if token.is_ascii() {
+        (Cow::Owned(token.to_ascii_lowercase()), None)
+    } else {
+        let (lowercase, indices) = lowercase_with_indices(token);
+        (Cow::Owned(lowercase), Some(indices))
+    };
+
+    // Special case for whole-word identifiers: don't look at word bounds, expect the whole token
+    // to match
+    let matches: Vec<(usize, usize)> = if is_id && whole_word {
+        if token == pattern {
+            vec![(0, token.len())]
+        } else {
+            vec![]
+        }
+    } else {
+        // In other cases we'll find the pattern in the token (which may occur multiple times) and
+        // check word boundaries when necessary
+        token
+            .match_indices(pattern)
+            .map(|(match_begin, _)| (match_begin, match_begin + pattern.len()))
+            .filter(|(match_begin, match_end)| {
+                !whole_word || check_word_bounds(token.as_ref(), *match_begin, *match_end)
+            })
+            .collect()
+    };
+
+    match token_indices {
+        None => matches,
+        // Matches that begin or end in the middle of the lowercase version of a character don't
+        // match whole characters of the token, skip these
+        Some(token_indices) => matches
+            .into_iter()
+            .filter_map(|(match_begin, match_end)| {
+                Some((token_indices[match_begin]?, token_indices[match_end]?))
+            })
+            .collect(),
+    }
+}
+
+/// Returns the byte range of the shortest part of `token` with the characters of `pattern` in
+/// order, not necessarily next to each other (`--fuzzy`). Of the shortest parts the first one is
+/// returned. Matches don't span multiple lines. When not `case_sensitive`, `pattern` should be
+/// lowercased with `lowercase`.
+fn match_token_fuzzy(token: &str, pattern: &str, case_sensitive: bool) -> Vec<(usize, usize)> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.is_empty() {
+        return vec![];
+    }
+
+    let mut shortest: Option<(usize, usize)> = None;
+    let mut line_start = 0;
+    for line in token.split('\n') {
+        for (start, _) in line.char_indices() {
+            // Characters matched at their first occurrences give the shortest part starting at
+            // `start`. When the pattern is not found after `start`, it won't be found after the
+            // next starts either.
+            let end = match fuzzy_chars(&line[start..], &pattern, case_sensitive) {
+                Some(chars) if chars[0].0 == 0 => start + chars.last().unwrap().1,
+                Some(_) => continue,
+                None => break,
+            };
+            if shortest.is_none_or(|(shortest_start, shortest_end)| {
+                end - start < shortest_end - shortest_start
+            }) {
+                shortest = Some((line_start + start, line_start + end));
+            }
+        }
+        line_start += line.len() + 1;
+    }
+
+    shortest.into_iter().collect()
+}
+
+/// Byte ranges of the characters of `pattern` in the `--fuzzy` match `match_text`, with
+/// consecutive characters merged into one range. When not `case_sensitive`, `pattern` should be
+/// lowercased with `lowercase`.
+pub(crate) fn fuzzy_match_spans(
+    match_text: &str,
+    pattern: &str,
+    case_sensitive: bool,
+) -> Vec<(usize, usize)> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut spans: Vec<(usize, usize)> = vec![];
+    for (char_start, char_end) in
+        fuzzy_chars(match_text, &pattern, case_sensitive).unwrap_or_default()
+    {
+        match spans.last_mut() {
+            Some((_, span_end)) if *span_end == char_start => *span_end = char_end,
+            _ => spans.push((char_start, char_end)),
+        }
+    }
+    spans
+}
+
+/// Byte ranges of the first occurrences of the characters of `pattern` in `text`, in order.
+/// `None` when `text` doesn't have all of the characters.
+fn fuzzy_chars(text: &str, pattern: &[char], case_sensitive: bool) -> Option<Vec<(usize, usize)>> {
+    let chars_match = |text_char: char, pattern_char: char| {
+        text_char == pattern_char
+            || (!case_sensitive && text_char.to_lowercase().eq(std::iter::once(pattern_char)))
+    };
+
+    let mut chars = Vec::with_capacity(pattern.len());
+    let mut pattern_chars = pattern.iter().peekable();
+    for (char_idx, char) in text.char_indices() {
+        match pattern_chars.peek() {
+            None => break,
+            Some(pattern_char) if chars_match(char, **pattern_char) => {
+                pattern_chars.next();
+                chars.push((char_idx, char_idx + char.len_utf8()));
+            }
+            Some(_) => {}
+        }
+    }
+
+    if pattern_chars.peek().is_some() {
+        None
+    } else {
+        Some(chars)
+    }
+}
+
+/// Returns byte ranges of matches of `regex` in `token`
+fn match_token_regex(
+    token: &str,
+    regex: &RegexPattern,
+    is_id: bool,
+    whole_word: bool,
+) -> Vec<(usize, usize)> {
+    // Same as `match_token`: whole-word identifiers need to match the whole token
+    if is_id && whole_word {
+        return if regex.anchored.is_match(token) {
+            vec![(0, token.len())]
+        } else {
+            vec![]
+        };
+    }
+
+    regex
+        .regex
+        .find_iter(token)
+        .filter(|match_| {
+            !match_.range().is_empty()
+                && (!whole_word || check_word_bounds(token, match_.start(), match_.end()))
+        })
+        .map(|match_| (match_.start(), match_.end()))
+        .collect()
+}
+
+/// Returns the text between the quotes of the character literal `token` (e.g. `x` in `b'x'`), and
+/// byte index of the text in `token`
+pub(crate) fn char_literal_contents(token: &str) -> (&str, usize) {
+    match (token.find('\''), token.rfind('\'')) {
+        (Some(begin), Some(end)) if begin < end => (&token[begin + 1..end], begin + 1),
+        _ => (token, 0),
+    }
+}
+
+/// Whether a regex has uppercase characters, ignoring escape sequences like `\W` and `\S`. Used
+/// to implement smart case for regexes.
+pub(crate) fn has_uppercase_literal(regex: &str) -> bool {
+    let mut chars = regex.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let _ = chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Returns 0-based line number (relative to the token start) and byte index in the line of the
+/// byte at `byte_idx` in `token`. Lines are terminated by "\n", same as in `line_starts`.
+fn get_token_line_col(token: &str, byte_idx: usize) -> (usize, usize) {
+    let token_prefix = &token[..byte_idx];
+    match token_prefix.rfind('\n') {
+        Some(last_newline) => (
+            token_prefix.matches('\n').count(),
+            byte_idx - last_newline - 1,
+        ),
+        None => (0, byte_idx),
+    }
+}
+
+/// Returns the 0-based column after `line_prefix`: number of characters in `line_prefix`, with
+/// tabs counted as `tab_width` columns
+fn get_column(line_prefix: &str, tab_width: usize) -> usize {
+    line_prefix
+        .chars()
+        .map(|c| if c == '\t' { tab_width } else { 1 })
+        .sum()
+}
+
+#[test]
+fn test_word_bounds() {
+    assert!(check_word_bounds("test", 0, 4));
+    assert!(!check_word_bounds("test", 0, 3));
+    assert!(!check_word_bounds("test", 1, 4));
+    assert!(!check_word_bounds("test", 1, 3));
+    assert!(!check_word_bounds("test", 1, 2));
+    assert!(!check_word_bounds("test", 2, 3));
+    assert!(!check_word_bounds("test", 2, 2));
+
+    assert!(check_word_bounds("a b c", 2, 3));
+    assert!(!check_word_bounds("a b c", 2, 4));
+    assert!(check_word_bounds("a b c", 2, 5));
+
+    // Digits and underscores are word characters
+    assert!(!check_word_bounds("foo_bar", 0, 3));
+    assert!(!check_word_bounds("foo2", 0, 3));
+    assert!(!check_word_bounds("_foo", 1, 4));
+    assert!(check_word_bounds("foo.bar", 0, 3));
+    assert!(check_word_bounds("(foo)", 1, 4));
+}
+
+#[test]
+fn test_match_token() {
+    assert_eq!(
+        match_token("test", "test", false, false, false),
+        vec![(0, 4)]
+    );
+    assert_eq!(
+        match_token("test", "test", true, false, false),
+        vec![(0, 4)]
+    );
+    assert_eq!(match_token("test", "Test", true, true, true), vec![]);
+    assert_eq!(match_token("Test", "Test", true, true, true), vec![(0, 4)]);
+
+    // Empty pattern
+    assert_eq!(match_token("test", "", false, false, false), vec![(0, 4)]);
+    assert_eq!(match_token("", "", false, false, false), vec![]);
+
+    // Whole word
+    assert_eq!(
+        match_token("just testing", "test", false, false, false),
+        vec![(5, 9)]
+    );
+    assert_eq!(
+        match_token("just testing", "test", false, true, false),
+        vec![]
+    );
+
+    // Multiple occurrences in single token
+    assert_eq!(
+        match_token("tey te tey", "te", false, false, false),
+        vec![(0, 2), (4, 6), (7, 9)]
+    );
+    assert_eq!(
+        match_token("tey te tey", "te", false, true, false),
+        vec![(4, 6)]
+    );
+    assert_eq!(
+        match_token("tey Te tey", "Te", false, false, true),
+        vec![(4, 6)]
+    );
+
+    // Lowercase of 'İ' (2 bytes) is "i̇" (3 bytes), match ranges should be in the original token
+    assert_eq!(
+        match_token("İstanbul_İzmir", "izmir", false, false, false),
+        vec![]
+    );
+    assert_eq!(
+        match_token("İstanbul_İzmir", "i̇zmir", false, false, false),
+        vec![(10, 16)]
+    );
+    assert_eq!(
+        match_token("İstanbul_İzmir", "stanbul", false, false, false),
+        vec![(2, 9)]
+    );
+    assert_eq!(
+        match_token("İstanbul", "i̇stanbul", true, true, false),
+        vec![(0, 9)]
+    );
+    // Lowercase of 'ẞ' (3 bytes) is 'ß' (2 bytes)
+    assert_eq!(
+        match_token("GROẞE_STRAẞE", "straße", false, false, false),
+        vec![(8, 16)]
+    );
+    assert_eq!(
+        match_token("ẞ_ẞ", &lowercase("ẞ"), false, false, false),
+        vec![(0, 3), (4, 7)]
+    );
+
+    // Each character is lowercased independently, final sigma is lowercased as 'σ' in both the
+    // pattern and the token
+    assert_eq!(
+        match_token("ΟΔΟΣ_ΟΔΟΣ", &lowercase("ΟΔΟΣ"), false, false, false),
+        vec![(0, 8), (9, 17)]
+    );
+}
+
+#[test]
+fn test_match_token_regex() {
+    let regex = RegexPattern::new("t[a-z]*t", true).unwrap();
+    assert_eq!(
+        match_token_regex("test tat", &regex, false, false),
+        vec![(0, 4), (5, 8)]
+    );
+    assert_eq!(
+        match_token_regex("testing tat", &regex, false, true),
+        vec![(8, 11)]
+    );
+    assert_eq!(match_token_regex("test", &regex, true, true), vec![(0, 4)]);
+    assert_eq!(match_token_regex("tests", &regex, true, true), vec![]);
+
+    let regex = RegexPattern::new("te.t", false).unwrap();
+    assert_eq!(
+        match_token_regex("TEST", &regex, false, false),
+        vec![(0, 4)]
+    );
+
+    assert!(!has_uppercase_literal("\\w+\\S"));
+    assert!(has_uppercase_literal("\\w+S"));
+}
+
+#[test]
+fn test_match_token_fuzzy() {
+    assert_eq!(
+        match_token_fuzzy("parse_config", "pcfg", true),
+        vec![(0, 12)]
+    );
+    assert_eq!(
+        match_token_fuzzy("parse_config", "pcf", true),
+        vec![(0, 10)]
+    );
+    assert_eq!(match_token_fuzzy("parse_config", "gp", true), vec![]);
+    assert_eq!(match_token_fuzzy("parse_config", "", true), vec![]);
+
+    // The shortest part is reported
+    assert_eq!(match_token_fuzzy("a_ab_b", "ab", true), vec![(2, 4)]);
+    assert_eq!(match_token_fuzzy("a_b_ab", "ab", true), vec![(4, 6)]);
+
+    // Case insensitive matching lowercases the token
+    assert_eq!(
+        match_token_fuzzy("parseConfig", "pcfg", false),
+        vec![(0, 11)]
+    );
+    assert_eq!(match_token_fuzzy("parseConfig", "pcfg", true), vec![]);
+    assert_eq!(match_token_fuzzy("prsCfg", "prscfg", false), vec![(0, 6)]);
+
+    // Byte ranges of non-ASCII tokens
+    assert_eq!(match_token_fuzzy("çöğ_ü", "çü", true), vec![(0, 9)]);
+
+    // Matches don't span lines
+    assert_eq!(match_token_fuzzy("a\nb", "ab", true), vec![]);
+    assert_eq!(match_token_fuzzy("a\nb ab", "ab", true), vec![(4, 6)]);
+}
+
+#[test]
+fn test_fuzzy_match_spans() {
+    assert_eq!(
+        fuzzy_match_spans("parse_config", "pcfg", true),
+        vec![(0, 1), (6, 7), (9, 10), (11, 12)]
+    );
+    assert_eq!(
+        fuzzy_match_spans("parseConfig", "parcon", false),
+        vec![(0, 3), (5, 8)]
+    );
+    assert_eq!(fuzzy_match_spans("çöğ", "çğ", true), vec![(0, 2), (4, 6)]);
+}
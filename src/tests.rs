@@ -1,22 +1,47 @@
-use std::convert::TryFrom;
 use std::ffi::OsString;
 
 use crate::run;
 
 fn run_args(args: &[&str]) -> String {
+    run_args_stdin(args, "")
+}
+
+fn run_args_stdin(args: &[&str], stdin: &str) -> String {
+    let (ret, stdout) = run_args_ret(args, stdin);
+    assert_eq!(ret, 0);
+    stdout
+}
+
+/// Run with `args`, expecting no matches
+fn run_args_no_match(args: &[&str]) -> String {
+    let (ret, stdout) = run_args_ret(args, "");
+    assert_eq!(ret, 1);
+    stdout
+}
+
+fn run_args_ret(args: &[&str], stdin: &str) -> (i32, String) {
+    let (ret, stdout, _) = run_args_stderr(args, stdin);
+    (ret, stdout)
+}
+
+/// Run with `args`, returning the exit code, stdout, and the statistics printed to stderr
+fn run_args_stderr(args: &[&str], stdin: &str) -> (i32, String, String) {
     let mut stdout: Vec<u8> = vec![];
+    let mut stderr: Vec<u8> = vec![];
 
     let ret = run(
         &mut stdout,
-        args.iter().map(|arg| OsString::try_from(arg).unwrap()),
+        &mut stderr,
+        &mut stdin.as_bytes(),
+        args.iter().map(OsString::from),
         true,
     );
 
-    assert_eq!(ret, 0);
-
-    let str = String::from_utf8(stdout).unwrap().to_owned();
-
-    str
+    (
+        ret,
+        String::from_utf8(stdout).unwrap(),
+        String::from_utf8(stderr).unwrap(),
+    )
 }
 
 #[test]
@@ -35,10 +60,9 @@ fn simple() {
     assert_eq!(
         str,
         "test_files/simple/simple.rs\n\
-         3:    let s = \"test\";\n\
-         2:    let s = \"testtest\";\n\
+         1:fn test() {\n\
          2:    let s = \"testtest\";\n\
-         1:fn test() {\n"
+         3:    let s = \"test\";\n"
     );
 }
 
@@ -59,8 +83,30 @@ fn simple_word() {
     assert_eq!(
         str,
         "test_files/simple/simple.rs\n\
-         3:    let s = \"test\";\n\
-         1:fn test() {\n"
+         1:fn test() {\n\
+         3:    let s = \"test\";\n"
+    );
+}
+
+#[test]
+fn simple_word_nogroup() {
+    // Whole word matches are written to the output handle, with file names on each line
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "-k",
+        "string,identifier",
+        "--nocolor",
+        "--nogroup",
+        "-w",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:1:fn test() {\n\
+         test_files/simple/simple.rs:3:    let s = \"test\";\n"
     );
 }
 
@@ -120,3 +166,3066 @@ fn issue_5_2() {
          \u{1b}[1;33m3\u{1b}[0m:32:let checkpoint_max_count = ref \u{1b}[43;30m1\u{1b}[0m5\n"
     );
 }
+
+#[test]
+fn ocaml_interface() {
+    let str = run_args(&[
+        "sg",
+        "--ocaml",
+        "counter",
+        "test_files/ocaml_interface",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/ocaml_interface/counter.mli\n\
+         1:val counter : int ref\n\
+         2:val incr_counter : unit -> unit\n"
+    );
+}
+
+#[test]
+fn go() {
+    let str = run_args(&["sg", "--go", "greet", "test_files/go", "--nocolor"]);
+    assert_eq!(
+        str,
+        "test_files/go/main.go\n\
+         4:func greet(name string) {\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--go",
+        "greet",
+        "test_files/go",
+        "--nocolor",
+        "--column",
+        "-k",
+        "comment,string",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/go/main.go\n\
+         3:4:// greet prints a greeting\n\
+         5:15:\tfmt.Println(\"greeting\", name, `raw greeting`, 'g')\n"
+    );
+}
+
+#[test]
+fn python_id() {
+    let str = run_args(&["sg", "--python", "test", "test_files/python", "--nocolor"]);
+
+    assert_eq!(
+        str,
+        "test_files/python/simple.py\n\
+         1:def test():\n"
+    );
+}
+
+#[test]
+fn python_comment_string() {
+    let str = run_args(&[
+        "sg",
+        "--python",
+        "test",
+        "test_files/python",
+        "-k",
+        "comment,string",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/python/simple.py\n\
+         2:    # test\n\
+         3:    s = \"testing\"\n"
+    );
+}
+
+#[test]
+fn ts_template_string() {
+    let str = run_args(&[
+        "sg",
+        "--ts",
+        "hello",
+        "test_files/ts",
+        "-k",
+        "string",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/ts/simple.ts\n\
+         2:    return `hello ${name}`;\n"
+    );
+}
+
+#[test]
+fn ts_template_substitution() {
+    // Identifiers in template substitutions are searched
+    let str = run_args(&["sg", "--ts", "name", "test_files/ts", "--nocolor"]);
+
+    assert_eq!(
+        str,
+        "test_files/ts/simple.ts\n\
+         1:function greet(name: string): string {\n\
+         2:    return `hello ${name}`;\n"
+    );
+}
+
+#[test]
+fn python_fstring_interpolation() {
+    let str = run_args(&["sg", "--python", "name", "test_files/fstring", "--nocolor"]);
+
+    assert_eq!(
+        str,
+        "test_files/fstring/fstring.py\n\
+         1:def greet(name):\n\
+         2:    return f\"hello {name}\"\n"
+    );
+}
+
+#[test]
+fn tsx() {
+    let str = run_args(&["sg", "--ts", "name", "test_files/tsx", "--nocolor"]);
+
+    assert_eq!(
+        str,
+        "test_files/tsx/simple.tsx\n\
+         1:function Greet(props: { name: string }) {\n\
+         2:    return <div className=\"greeting\">{props.name}</div>;\n"
+    );
+}
+
+#[test]
+fn js_exts() {
+    let str = run_args(&[
+        "sg",
+        "--js",
+        "hello",
+        "test_files/js/common.cjs",
+        "test_files/js/module.mjs",
+        "test_files/js/simple.jsx",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/js/common.cjs\n\
+         1:module.exports.hello = 1;\n\
+         \n\
+         test_files/js/module.mjs\n\
+         1:export const hello = 1;\n\
+         \n\
+         test_files/js/simple.jsx\n\
+         1:const hello = \"hello\";\n"
+    );
+}
+
+#[test]
+fn multiple_paths() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple/simple.rs",
+        "test_files/does_not_exist",
+        "test_files/simple",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         1:fn test() {\n\
+         \n\
+         test_files/simple/simple.rs\n\
+         1:fn test() {\n"
+    );
+}
+
+#[test]
+fn json() {
+    let str = run_args(&["sg", "--rust", "test", "test_files/simple", "--json"]);
+
+    let matches: Vec<serde_json::Value> = str
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(
+        matches,
+        vec![serde_json::json!({
+            "path": "test_files/simple/simple.rs",
+            "line": 1,
+            "column": 4,
+            "byte_offset": 3,
+            "match_text": "test",
+            "line_text": "fn test() {",
+            "node_kind": "identifier",
+        })]
+    );
+}
+
+#[test]
+fn json_pretty() {
+    // Objects are printed on multiple lines, with the capture names of query matches
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        "(call_expression function: (identifier) @f)",
+        "--each-capture",
+        "test_files/query",
+        "--json",
+        "--json-pretty",
+    ]);
+    assert_eq!(
+        str,
+        "{\n  \"byte_offset\": 69,\n  \"capture\": \"f\",\n  \"column\": 13,\n  \"line\": 6,\n  \
+         \"line_text\": \"    let x = add(1, 2);\",\n  \"match_text\": \"add\",\n  \
+         \"node_kind\": \"identifier\",\n  \"path\": \"test_files/query/query.rs\"\n}\n"
+    );
+
+    let (ret, str) = run_args_ret(
+        &["sg", "--rust", "test", "test_files/simple", "--json-pretty"],
+        "",
+    );
+    assert_eq!(ret, 2);
+    assert_eq!(str, "");
+}
+
+#[test]
+fn fuzzy() {
+    // Characters of the pattern are matched in order, not necessarily next to each other
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "pcfg",
+        "--fuzzy",
+        "test_files/fuzzy",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/fuzzy/fuzzy.rs:1:4:fn parse_config() {}\n\
+         test_files/fuzzy/fuzzy.rs:3:4:fn print_config_file() {}\n\
+         test_files/fuzzy/fuzzy.rs:6:5:    parse_config();\n"
+    );
+
+    // The shortest matching part of each token is a match
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "pcf",
+        "--fuzzy",
+        "test_files/fuzzy",
+        "--json",
+    ]);
+    let match_texts: Vec<String> = str
+        .lines()
+        .map(|line| {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            json["match_text"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(match_texts, vec!["parse_conf", "print_conf", "parse_conf"]);
+
+    run_args_no_match(&["sg", "--rust", "prsfile", "--fuzzy", "test_files/fuzzy"]);
+
+    // Only the matched characters are highlighted
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "pcfg",
+        "--fuzzy",
+        "test_files/fuzzy/fuzzy.rs",
+        "--nogroup",
+        "--max-count",
+        "1",
+    ]);
+    assert_eq!(
+        str,
+        "\u{1b}[1;32mtest_files/fuzzy/fuzzy.rs\u{1b}[0m:\u{1b}[1;33m1\u{1b}[0m:fn \
+         \u{1b}[43;30mp\u{1b}[0marse_\u{1b}[43;30mc\u{1b}[0mon\u{1b}[43;30mf\u{1b}[0mi\
+         \u{1b}[43;30mg\u{1b}[0m() {}\n"
+    );
+}
+
+#[test]
+fn multiple_patterns() {
+    // Matches of any of the patterns are reported, first positional argument is a path
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-e",
+        "test",
+        "-e",
+        "s",
+        "-k",
+        "identifier,string",
+        "test_files/simple",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         1:fn test() {\n\
+         2:    let s = \"testtest\";\n\
+         3:    let s = \"test\";\n"
+    );
+
+    // Of the overlapping matches the longest is reported, with the index of its pattern
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-e",
+        "tes",
+        "-e",
+        "testt",
+        "-k",
+        "string",
+        "test_files/simple",
+        "--json",
+    ]);
+    let matches: Vec<(String, u64)> = str
+        .lines()
+        .map(|line| {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            (
+                json["match_text"].as_str().unwrap().to_owned(),
+                json["pattern"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        matches,
+        vec![("testt".to_owned(), 1), ("tes".to_owned(), 0)]
+    );
+
+    // Patterns from a file, combined with `-e`
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-f",
+        "test_files/pattern_file/patterns.txt",
+        "-e",
+        "fn",
+        "test_files/simple",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:1:1:fn test() {\n\
+         test_files/simple/simple.rs:1:4:fn test() {\n\
+         test_files/simple/simple.rs:2:5:    let s = \"testtest\";\n\
+         test_files/simple/simple.rs:3:5:    let s = \"test\";\n"
+    );
+
+    // Smart case: each pattern is matched case sensitively when it has an uppercase character
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-e",
+        "TEST",
+        "-e",
+        "let",
+        "-e",
+        "FN",
+        "test_files/simple",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:2:5:    let s = \"testtest\";\n\
+         test_files/simple/simple.rs:3:5:    let s = \"test\";\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-e",
+        "TEST",
+        "-e",
+        "fn",
+        "-i",
+        "test_files/simple",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:1:1:fn test() {\n\
+         test_files/simple/simple.rs:1:4:fn test() {\n"
+    );
+
+    let (ret, str) = run_args_ret(
+        &[
+            "sg",
+            "--rust",
+            "-f",
+            "test_files/pattern_file/missing.txt",
+            "test_files/simple",
+        ],
+        "",
+    );
+    assert_eq!(ret, 2);
+    assert_eq!(str, "");
+}
+
+#[test]
+fn context() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "let",
+        "test_files/simple",
+        "--nocolor",
+        "-C",
+        "1",
+    ]);
+
+    // Context lines of the two matches overlap, lines are printed once
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         1-fn test() {\n\
+         2:    let s = \"testtest\";\n\
+         3:    let s = \"test\";\n\
+         4-    // wow\n"
+    );
+}
+
+#[test]
+fn context_nogroup() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "--nocolor",
+        "--nogroup",
+        "-A",
+        "1",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:1:fn test() {\n\
+         test_files/simple/simple.rs-2-    let s = \"testtest\";\n"
+    );
+}
+
+#[test]
+fn count() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "-k",
+        "string,identifier,comment",
+        "--nocolor",
+        "-c",
+    ]);
+
+    assert_eq!(str, "test_files/simple/simple.rs: 4\n");
+}
+
+#[test]
+fn count_zero() {
+    let str = run_args_no_match(&[
+        "sg",
+        "--rust",
+        "wow",
+        "test_files/simple",
+        "--nocolor",
+        "-c",
+        "--count-zero",
+    ]);
+
+    assert_eq!(str, "test_files/simple/simple.rs: 0\n");
+}
+
+#[test]
+fn threads() {
+    // Output should be the same regardless of number of threads
+    let single_thread = run_args(&[
+        "sg",
+        "--rust",
+        "a",
+        "test_files/multi/a.rs",
+        "test_files/multi/b.rs",
+        "test_files/multi/a.rs",
+        "--nocolor",
+        "--threads",
+        "1",
+    ]);
+
+    assert_eq!(
+        single_thread,
+        "test_files/multi/a.rs\n\
+         1:fn a() {}\n\
+         \n\
+         test_files/multi/b.rs\n\
+         2:    a();\n\
+         \n\
+         test_files/multi/a.rs\n\
+         1:fn a() {}\n"
+    );
+
+    let multi_thread = run_args(&[
+        "sg",
+        "--rust",
+        "a",
+        "test_files/multi/a.rs",
+        "test_files/multi/b.rs",
+        "test_files/multi/a.rs",
+        "--nocolor",
+        "--threads",
+        "4",
+    ]);
+
+    assert_eq!(single_thread, multi_thread);
+}
+
+#[test]
+fn multiple_matches_in_line() {
+    // Line with multiple matches is printed once, with all matches highlighted. Adjacent matches
+    // are highlighted as one.
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "-k",
+        "string",
+        "--column",
+    ]);
+
+    assert_eq!(
+        str,
+        "\u{1b}[1;32mtest_files/simple/simple.rs\u{1b}[0m\n\
+         \u{1b}[1;33m2\u{1b}[0m:14:    let s = \"\u{1b}[43;30mtesttest\u{1b}[0m\";\n\
+         \u{1b}[1;33m3\u{1b}[0m:14:    let s = \"\u{1b}[43;30mtest\u{1b}[0m\";\n"
+    );
+}
+
+#[test]
+fn regex() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--regex",
+        "T[a-z]+T",
+        "-i",
+        "test_files/simple",
+        "-k",
+        "string",
+        "--nocolor",
+        "--json",
+    ]);
+
+    let match_texts: Vec<String> = str
+        .lines()
+        .map(|line| {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            json["match_text"].as_str().unwrap().to_owned()
+        })
+        .collect();
+
+    assert_eq!(match_texts, vec!["testtest", "test"]);
+}
+
+#[test]
+fn files_with_matches() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "a",
+        "test_files/multi/a.rs",
+        "test_files/multi/b.rs",
+        "test_files/simple",
+        "--nocolor",
+        "-l",
+    ]);
+
+    assert_eq!(str, "test_files/multi/a.rs\ntest_files/multi/b.rs\n");
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "a",
+        "test_files/multi/a.rs",
+        "test_files/multi/b.rs",
+        "test_files/simple",
+        "--nocolor",
+        "-L",
+    ]);
+
+    assert_eq!(str, "test_files/simple/simple.rs\n");
+}
+
+#[test]
+fn null() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "a",
+        "test_files/multi/a.rs",
+        "test_files/multi/b.rs",
+        "test_files/simple",
+        "--nocolor",
+        "-l",
+        "-0",
+    ]);
+
+    assert_eq!(str, "test_files/multi/a.rs\0test_files/multi/b.rs\0");
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "-k",
+        "string,identifier,comment",
+        "--nocolor",
+        "-c",
+        "--null",
+    ]);
+
+    assert_eq!(str, "test_files/simple/simple.rs\x004\n");
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-k",
+        "string",
+        "test",
+        "test_files/simple",
+        "--nocolor",
+        "--nogroup",
+        "--null",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\x002:    let s = \"testtest\";\n\
+         test_files/simple/simple.rs\x003:    let s = \"test\";\n"
+    );
+}
+
+#[test]
+fn def() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "search",
+        "test_files/def",
+        "--nocolor",
+        "--def",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/def/def.rs\n\
+         2:    fn search(&self);\n\
+         5:fn search_all(items: &[&dyn Search]) {\n\
+         12:    fn search(&self) {}\n"
+    );
+}
+
+#[test]
+fn smart_case() {
+    let both = "test_files/casing/casing.rs\n\
+                1:fn foo() {}\n\
+                2:fn Foo() {}\n";
+    let upper = "test_files/casing/casing.rs\n\
+                 2:fn Foo() {}\n";
+
+    let search = |args: &[&str]| {
+        let mut all_args = vec!["sg", "--rust", "--nocolor"];
+        all_args.extend(args);
+        all_args.push("test_files/casing");
+        run_args(&all_args)
+    };
+
+    // Literal patterns
+    assert_eq!(search(&["foo"]), both);
+    assert_eq!(search(&["Foo"]), upper);
+    assert_eq!(search(&["Foo", "-i"]), both);
+
+    // Regexes
+    assert_eq!(search(&["--regex", "f.o"]), both);
+    assert_eq!(search(&["--regex", "F.o"]), upper);
+
+    // Query capture texts
+    let query = "(function_item name: (identifier) @name)";
+    assert_eq!(search(&["--qs", query, "--capture", "name=foo"]), both);
+    assert_eq!(search(&["--qs", query, "--capture", "name=Foo"]), upper);
+    assert_eq!(
+        search(&["--qs", query, "--capture", "name=Foo", "-i"]),
+        both
+    );
+}
+
+#[test]
+fn invert_match() {
+    // Line 4 has a comment, but we don't search comments, so it's not printed. Line 5 only has
+    // punctuation, which is not searched either.
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "--nocolor",
+        "-v",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         2:    let s = \"testtest\";\n\
+         3:    let s = \"test\";\n"
+    );
+}
+
+#[test]
+fn replace_dry_run() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "s",
+        "test_files/simple",
+        "--replace",
+        "str",
+        "--dry-run",
+    ]);
+
+    assert_eq!(
+        str,
+        "--- test_files/simple/simple.rs\n\
+         +++ test_files/simple/simple.rs\n\
+         @@ -1,5 +1,5 @@\n \
+         fn test() {\n\
+         -    let s = \"testtest\";\n\
+         -    let s = \"test\";\n\
+         +    let str = \"testtest\";\n\
+         +    let str = \"test\";\n     \
+         // wow\n \
+         }\n"
+    );
+}
+
+#[test]
+fn replace_preview() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "s",
+        "test_files/simple",
+        "--replace",
+        "str",
+        "--preview",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         2:    let [-s-]{+str+} = \"testtest\";\n\
+         3:    let [-s-]{+str+} = \"test\";\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "s",
+        "test_files/simple/simple.rs",
+        "--replace",
+        "str",
+        "--preview",
+        "--nogroup",
+    ]);
+
+    assert_eq!(
+        str,
+        "\u{1b}[1;32mtest_files/simple/simple.rs\u{1b}[0m:\u{1b}[1;33m2\u{1b}[0m:    let \
+         \u{1b}[9;31ms\u{1b}[0m\u{1b}[32mstr\u{1b}[0m = \"testtest\";\n\
+         \u{1b}[1;32mtest_files/simple/simple.rs\u{1b}[0m:\u{1b}[1;33m3\u{1b}[0m:    let \
+         \u{1b}[9;31ms\u{1b}[0m\u{1b}[32mstr\u{1b}[0m = \"test\";\n"
+    );
+}
+
+#[test]
+fn replace() {
+    let dir = std::env::temp_dir().join(format!("sg_replace_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("simple.rs");
+    std::fs::copy("test_files/simple/simple.rs", &file).unwrap();
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        file.to_str().unwrap(),
+        "--replace",
+        "check",
+    ]);
+    assert_eq!(str, "");
+
+    let new_contents = std::fs::read_to_string(&file).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        new_contents,
+        "fn check() {\n    let s = \"testtest\";\n    let s = \"test\";\n    // wow\n}\n"
+    );
+}
+
+#[test]
+fn number() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "42",
+        "test_files/number",
+        "-k",
+        "number",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/number/number.rs\n\
+         2:    let x = 42;\n\
+         3:    let y = 42.5;\n"
+    );
+
+    // Number literals are also searched by default, as identifiers
+    let str = run_args(&["sg", "--rust", "42", "test_files/number", "--nocolor"]);
+    assert_eq!(
+        str,
+        "test_files/number/number.rs\n\
+         2:    let x = 42;\n\
+         3:    let y = 42.5;\n"
+    );
+}
+
+#[test]
+fn char() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "x",
+        "test_files/char",
+        "-k",
+        "char",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/char/char.rs\n\
+         2:    let c = 'x';\n"
+    );
+
+    // Quotes are not searched
+    let str = run_args_no_match(&["sg", "--rust", "'", "test_files/char", "-k", "char"]);
+    assert_eq!(str, "");
+}
+
+#[test]
+fn crlf() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/crlf",
+        "--column",
+        "-k",
+        "identifier,string",
+    ]);
+
+    assert_eq!(
+        str,
+        "\u{1b}[1;32mtest_files/crlf/crlf.rs\u{1b}[0m\n\
+         \u{1b}[1;33m2\u{1b}[0m:9:    let \u{1b}[43;30mtest\u{1b}[0m = 1;\n\
+         \u{1b}[1;33m3\u{1b}[0m:9:    bar(\u{1b}[43;30mtest\u{1b}[0m, \"\u{1b}[43;30mtest\u{1b}[0m\");\n"
+    );
+}
+
+#[test]
+fn tab_width() {
+    let args = [
+        "sg",
+        "--rust",
+        "x",
+        "test_files/tab",
+        "--column",
+        "--nocolor",
+    ];
+
+    let str = run_args(&args);
+    assert_eq!(
+        str,
+        "test_files/tab/tab.rs\n\
+         2:6:\tlet x = 1;\n"
+    );
+
+    let mut args_tab_width = args.to_vec();
+    args_tab_width.extend(["--tab-width", "4"]);
+    let str = run_args(&args_tab_width);
+    assert_eq!(
+        str,
+        "test_files/tab/tab.rs\n\
+         2:9:\tlet x = 1;\n"
+    );
+}
+
+#[test]
+fn byte_offset() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-k",
+        "string",
+        "test",
+        "test_files/simple",
+        "--nocolor",
+        "-b",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         2:25:    let s = \"testtest\";\n\
+         3:49:    let s = \"test\";\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-k",
+        "string",
+        "test",
+        "test_files/simple",
+        "--nocolor",
+        "--column",
+        "--byte-offset",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         2:14:25:    let s = \"testtest\";\n\
+         3:14:49:    let s = \"test\";\n"
+    );
+}
+
+#[test]
+fn regex_multi_line_match() {
+    // Matches spanning multiple lines are highlighted on each line
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--regex",
+        "a\\s+b",
+        "-k",
+        "comment",
+        "test_files/multi_line",
+    ]);
+
+    assert_eq!(
+        str,
+        "\u{1b}[1;32mtest_files/multi_line/multi_line.rs\u{1b}[0m\n\
+         \u{1b}[1;33m1\u{1b}[0m:/* \u{1b}[43;30ma\u{1b}[0m\n\
+         \u{1b}[1;33m2\u{1b}[0m:\u{1b}[43;30m b\u{1b}[0m */\n"
+    );
+}
+
+#[test]
+fn multi_line_pattern() {
+    // Patterns with newlines match in multi-line tokens
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "a\n b",
+        "-k",
+        "comment",
+        "test_files/multi_line",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/multi_line/multi_line.rs\n\
+         1:/* a\n\
+         2: b */\n"
+    );
+}
+
+#[test]
+fn stdin() {
+    let str = run_args_stdin(
+        &["sg", "--rust", "test", "-", "--nocolor"],
+        "fn test() {}\nfn main() {}\n",
+    );
+
+    assert_eq!(
+        str,
+        "<stdin>\n\
+         1:fn test() {}\n"
+    );
+}
+
+#[test]
+fn replace_case_sensitive() {
+    let input = "fn foo() {}\nstruct Foo;\nconst FOO: i32 = 1;\n";
+
+    // Case sensitive by default, even though the pattern is lowercase
+    let str = run_args_stdin(&["sg", "--rust", "foo", "-", "--replace", "bar"], input);
+    assert_eq!(str, "fn bar() {}\nstruct Foo;\nconst FOO: i32 = 1;\n");
+
+    let str = run_args_stdin(
+        &["sg", "--rust", "foo", "-", "--replace", "bar", "-i"],
+        input,
+    );
+    assert_eq!(str, "fn bar() {}\nstruct bar;\nconst bar: i32 = 1;\n");
+}
+
+#[test]
+fn bare_cr() {
+    // "\r" without "\n" is not a line terminator
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/cr",
+        "-k",
+        "comment",
+        "--column",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/cr/cr.rs\n\
+         2:6:/* a\rtest */\n"
+    );
+}
+
+#[test]
+fn all_langs() {
+    let str = run_args(&[
+        "sg",
+        "--all",
+        "test",
+        "test_files/mixed",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    // Order of files in a directory is not specified
+    let mut lines: Vec<&str> = str.lines().collect();
+    lines.sort_unstable();
+
+    assert_eq!(
+        lines,
+        vec![
+            "test_files/mixed/a.rs:1:fn test() {}",
+            "test_files/mixed/b.py:1:def test():",
+        ]
+    );
+}
+
+#[test]
+fn vimgrep() {
+    // One line per match, even when a line has multiple matches
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "-k",
+        "string",
+        "--vimgrep",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:2:14:    let s = \"testtest\";\n\
+         test_files/simple/simple.rs:2:18:    let s = \"testtest\";\n\
+         test_files/simple/simple.rs:3:14:    let s = \"test\";\n"
+    );
+}
+
+#[test]
+fn query_string() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        "(call_expression function: (identifier) @f)",
+        "test_files/query",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         6:    let x = add(1, 2);\n"
+    );
+}
+
+#[test]
+fn query_name() {
+    std::env::set_var("XDG_CONFIG_HOME", "test_files/query_config");
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qn",
+        "fn-names",
+        "test_files/query",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         1:fn add(a: i32, b: i32) -> i32 {\n\
+         …\n\
+         5:fn main() {\n\
+         …\n"
+    );
+}
+
+#[test]
+fn query_builtin() {
+    std::env::set_var("XDG_CONFIG_HOME", "test_files/query_config");
+
+    let expected = [
+        (
+            "fn-defs",
+            "1:fn main() {\n…\n11:unsafe fn read() -> i32 {\n…\n",
+        ),
+        (
+            "panic-calls",
+            "5:        panic!(\"zero\");\n12:    unimplemented!()\n",
+        ),
+        (
+            "todo-comments",
+            "2:    // TODO: handle errors\n7:    /* FIXME */\n",
+        ),
+        ("unsafe-blocks", "3:    let x = unsafe { read() };\n"),
+    ];
+
+    for (name, lines) in expected {
+        let str = run_args(&[
+            "sg",
+            "--rust",
+            "--qn",
+            name,
+            "test_files/builtin_queries",
+            "--nocolor",
+        ]);
+        assert_eq!(
+            str,
+            format!("test_files/builtin_queries/builtin.rs\n{}", lines),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn list_queries() {
+    std::env::set_var("XDG_CONFIG_HOME", "test_files/query_config");
+
+    // Built-in and user queries of the language
+    let rust_queries = "fn-defs        Names of function definitions\n\
+                        fn-names       Names of functions\n\
+                        panic-calls    Uses of panic!, todo!, unimplemented!, and unreachable!\n\
+                        todo-comments  Comments with TODO, FIXME, or XXX\n\
+                        unsafe-blocks  `unsafe` blocks\n";
+    let str = run_args(&["sg", "--rust", "--list-queries"]);
+    assert_eq!(str, rust_queries);
+
+    // Without a language, queries of all languages
+    let str = run_args(&["sg", "--list-queries"]);
+    assert_eq!(
+        str,
+        format!(
+            "js\n\
+             console-log  Calls of console.log\n\
+             \n\
+             rust\n\
+             {}",
+            rust_queries
+        )
+    );
+}
+
+#[test]
+fn query_file() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--query-file",
+        "test_files/query_config/sg/queries/rust/fn-names.scm",
+        "test_files/query",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         1:fn add(a: i32, b: i32) -> i32 {\n\
+         …\n\
+         5:fn main() {\n\
+         …\n"
+    );
+
+    let (ret, str) = run_args_ret(
+        &[
+            "sg",
+            "--rust",
+            "--query-file",
+            "test_files/query/missing.scm",
+            "test_files/query",
+        ],
+        "",
+    );
+    assert_eq!(ret, 2);
+    assert_eq!(str, "");
+}
+
+#[test]
+fn query_each_capture() {
+    static QUERY: &str =
+        "(call_expression function: (identifier) @f arguments: (arguments (integer_literal) @n))";
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        QUERY,
+        "--each-capture",
+        "--vimgrep",
+        "test_files/query",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/query/query.rs:6:13:    let x = add(1, 2);\n\
+         test_files/query/query.rs:6:17:    let x = add(1, 2);\n\
+         test_files/query/query.rs:6:20:    let x = add(1, 2);\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        QUERY,
+        "--each-capture",
+        "-c",
+        "--nocolor",
+        "test_files/query",
+    ]);
+    assert_eq!(str, "test_files/query/query.rs: 3\n");
+
+    // Output formats of pattern matches need `--each-capture`
+    let (ret, str) = run_args_ret(
+        &[
+            "sg",
+            "--rust",
+            "--qs",
+            QUERY,
+            "--vimgrep",
+            "test_files/query",
+        ],
+        "",
+    );
+    assert_eq!(ret, 2);
+    assert_eq!(str, "");
+}
+
+#[test]
+fn query_replace() {
+    // Matched nodes are replaced, with `@name` replaced by the capture texts
+    static INPUT: &str = "fn main() {\n    let x = add(1, mul(2, 3));\n    send(x);\n}\n";
+    let str = run_args_stdin(
+        &[
+            "sg",
+            "--rust",
+            "--qs",
+            "(call_expression function: (identifier) @f arguments: (_) @args)",
+            "-",
+            "--replace",
+            "@f!@args /* @@ */",
+        ],
+        INPUT,
+    );
+    // Only the outermost nested matches are replaced
+    assert_eq!(
+        str,
+        "fn main() {\n    let x = add!(1, mul(2, 3)) /* @ */;\n    send!(x) /* @ */;\n}\n"
+    );
+
+    // Nested matches that are not replaced are not counted
+    let (ret, _, err) = run_args_stderr(
+        &[
+            "sg",
+            "--rust",
+            "--qs",
+            "(call_expression function: (identifier) @f arguments: (_) @args)",
+            "-",
+            "--replace",
+            "@f!@args",
+            "--count-matches",
+        ],
+        INPUT,
+    );
+    assert_eq!(ret, 0);
+    assert_eq!(err, "2 matches\n");
+
+    // Captures must exist in the query
+    let (ret, str) = run_args_ret(
+        &[
+            "sg",
+            "--rust",
+            "--qs",
+            "(call_expression function: (identifier) @f)",
+            "-",
+            "--replace",
+            "@g()",
+        ],
+        INPUT,
+    );
+    assert_eq!(ret, 2);
+    assert_eq!(str, "");
+}
+
+#[test]
+fn query_column() {
+    let args = ["sg", "--rust", "test_files/query", "--column", "--nocolor"];
+
+    // Column of the first capture in the line
+    let str = run_args(
+        &[
+            &args[..],
+            &["--qs", "(call_expression arguments: (_) @args)"],
+        ]
+        .concat(),
+    );
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         6:16:    let x = add(1, 2);\n"
+    );
+
+    // Without captures, column of the matched node
+    let str = run_args(&[&args[..], &["--qs", "(call_expression)"]].concat());
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         6:13:    let x = add(1, 2);\n"
+    );
+}
+
+#[test]
+fn query_group() {
+    let args = [
+        "sg",
+        "--rust",
+        "--qs",
+        "(call_expression) @c",
+        "test_files/query",
+        "test_files/query_capture",
+        "--nocolor",
+    ];
+
+    // Path is printed once before the matches of the file, files are separated with an empty line
+    let str = run_args(&args);
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         6:    let x = add(1, 2);\n\
+         \n\
+         test_files/query_capture/capture.rs\n\
+         2:    add(1, 2);\n\
+         3:    sub(3, 4);\n\
+         4:    mul(5, 6);\n"
+    );
+    assert_eq!(run_args(&[&args[..], &["--group"]].concat()), str);
+
+    // Path is printed in each matched line
+    let str = run_args(&[&args[..], &["--nogroup"]].concat());
+    assert_eq!(
+        str,
+        "test_files/query/query.rs:6:    let x = add(1, 2);\n\
+         test_files/query_capture/capture.rs:2:    add(1, 2);\n\
+         test_files/query_capture/capture.rs:3:    sub(3, 4);\n\
+         test_files/query_capture/capture.rs:4:    mul(5, 6);\n"
+    );
+}
+
+#[test]
+fn query_full_node() {
+    // Lines of the matched node without captures are printed as context
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        "(function_item name: (identifier) @name)",
+        "test_files/query",
+        "--full-node",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         1:fn add(a: i32, b: i32) -> i32 {\n\
+         2-    a + b\n\
+         3-}\n\
+         5:fn main() {\n\
+         6-    let x = add(1, 2);\n\
+         7-    println!(\"{}\", x);\n\
+         8-}\n"
+    );
+}
+
+#[test]
+fn query_context() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        "(binary_expression left: (identifier) @l right: (identifier) @r)",
+        "test_files/query",
+        "-B1",
+        "-A1",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         1-fn add(a: i32, b: i32) -> i32 {\n\
+         2:    a + b\n\
+         3-}\n"
+    );
+
+    // Without captures the first line of the node is printed
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        "(function_item)",
+        "test_files/query",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         1:fn add(a: i32, b: i32) -> i32 {\n\
+         …\n\
+         5:fn main() {\n\
+         …\n"
+    );
+}
+
+#[test]
+fn query_node_first_line() {
+    // The first line of the node is printed before the captures, lines that are not printed are
+    // elided
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        "(function_item body: (block (let_declaration) @let))",
+        "test_files/query",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         5-fn main() {\n\
+         6:    let x = add(1, 2);\n\
+         …\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        "(function_item body: (block (let_declaration) @let))",
+        "test_files/query",
+        "--full-node",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query/query.rs\n\
+         5-fn main() {\n\
+         6:    let x = add(1, 2);\n\
+         7-    println!(\"{}\", x);\n\
+         8-}\n"
+    );
+}
+
+#[test]
+fn query_capture() {
+    static QUERY: &str =
+        "(call_expression function: (identifier) @f arguments: (arguments . (integer_literal) @x))";
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        QUERY,
+        "--capture",
+        "f=sub",
+        "test_files/query_capture",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query_capture/capture.rs\n\
+         3:    sub(3, 4);\n"
+    );
+
+    // Matches with any of the captures are reported
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        QUERY,
+        "--capture",
+        "f=add",
+        "--capture",
+        "x=5",
+        "test_files/query_capture",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/query_capture/capture.rs\n\
+         2:    add(1, 2);\n\
+         4:    mul(5, 6);\n"
+    );
+
+    // Capture text should match exactly
+    let str = run_args_no_match(&[
+        "sg",
+        "--rust",
+        "--qs",
+        QUERY,
+        "--capture",
+        "f=ad",
+        "test_files/query_capture",
+        "--nocolor",
+    ]);
+
+    assert_eq!(str, "");
+
+    // Unknown capture names are an error
+    let mut stdout: Vec<u8> = vec![];
+    let ret = run(
+        &mut stdout,
+        &mut std::io::sink(),
+        &mut "".as_bytes(),
+        [
+            "sg",
+            "--rust",
+            "--qs",
+            QUERY,
+            "--capture",
+            "y=1",
+            "test_files/query_capture",
+        ]
+        .iter()
+        .map(OsString::from),
+        true,
+    );
+    assert_eq!(ret, 2);
+    assert!(stdout.is_empty());
+}
+
+#[test]
+fn query_color() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--qs",
+        "(call_expression function: (identifier) @f arguments: (arguments . (integer_literal) @x))",
+        "test_files/query_capture",
+    ]);
+
+    assert_eq!(
+        str,
+        "\u{1b}[1;32mtest_files/query_capture/capture.rs\u{1b}[0m\n\
+         \u{1b}[1;33m2\u{1b}[0m:    \u{1b}[43;30madd\u{1b}[0m(\u{1b}[43;30m1\u{1b}[0m, 2);\n\
+         \u{1b}[1;33m3\u{1b}[0m:    \u{1b}[43;30msub\u{1b}[0m(\u{1b}[43;30m3\u{1b}[0m, 4);\n\
+         \u{1b}[1;33m4\u{1b}[0m:    \u{1b}[43;30mmul\u{1b}[0m(\u{1b}[43;30m5\u{1b}[0m, 6);\n"
+    );
+}
+
+#[test]
+fn color_when() {
+    let colored = "\u{1b}[1;32mtest_files/query_capture/capture.rs\u{1b}[0m\n\
+                   \u{1b}[1;33m2\u{1b}[0m:    \u{1b}[43;30madd\u{1b}[0m(1, 2);\n";
+    let plain = "test_files/query_capture/capture.rs\n\
+                 2:    add(1, 2);\n";
+
+    // `--color` without a value doesn't take the next argument as its value
+    let str = run_args(&["sg", "--color", "add", "--rust", "test_files/query_capture"]);
+    assert_eq!(str, colored);
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "add",
+        "test_files/query_capture",
+        "--color=auto",
+    ]);
+    assert_eq!(str, colored);
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "add",
+        "test_files/query_capture",
+        "--color=never",
+    ]);
+    assert_eq!(str, plain);
+
+    // Last one of `--color` and `--nocolor` wins
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "add",
+        "test_files/query_capture",
+        "--nocolor",
+        "--color=always",
+    ]);
+    assert_eq!(str, colored);
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "add",
+        "test_files/query_capture",
+        "--color",
+        "--nocolor",
+    ]);
+    assert_eq!(str, plain);
+}
+
+#[test]
+fn max_count() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-k",
+        "string",
+        "test",
+        "test_files/simple",
+        "-m",
+        "2",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         2:    let s = \"testtest\";\n"
+    );
+
+    // Number of matches is counted for each file separately
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-r",
+        "^[a-z]$",
+        "test_files/simple/simple.rs",
+        "test_files/query/query.rs",
+        "--max-count",
+        "1",
+        "--count",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs: 1\n\
+         test_files/query/query.rs: 1\n"
+    );
+}
+
+#[test]
+fn max_total_matches() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-r",
+        "^[a-z]$",
+        "test_files/simple/simple.rs",
+        "test_files/query/query.rs",
+        "test_files/query_capture/capture.rs",
+        "--max-total-matches",
+        "3",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         2:    let s = \"testtest\";\n\
+         3:    let s = \"test\";\n\
+         \n\
+         test_files/query/query.rs\n\
+         1:fn add(a: i32, b: i32) -> i32 {\n"
+    );
+
+    // Limit is reached at the end of a file
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-r",
+        "^[a-z]$",
+        "test_files/simple/simple.rs",
+        "test_files/query/query.rs",
+        "--max-total-matches",
+        "2",
+        "--count",
+        "--nocolor",
+    ]);
+
+    assert_eq!(str, "test_files/simple/simple.rs: 2\n");
+
+    // Matches in files searched in parallel after the limit is reached are not counted
+    let (ret, str, stderr) = run_args_stderr(
+        &[
+            "sg",
+            "--rust",
+            "-r",
+            "^[a-z]$",
+            "test_files/simple",
+            "test_files/query",
+            "test_files/query_capture",
+            "test_files/builtin_queries",
+            "test_files/context",
+            "test_files/number",
+            "test_files/exclude",
+            "--max-total-matches",
+            "1",
+            "--count-matches",
+            "--threads",
+            "4",
+            "--nocolor",
+            "--nogroup",
+        ],
+        "",
+    );
+
+    assert_eq!(ret, 0);
+    assert_eq!(str.lines().count(), 1);
+    assert_eq!(stderr, "1 matches\n");
+}
+
+#[test]
+fn upper_case_ext() {
+    let str = run_args(&["sg", "--rust", "upper", "test_files/upper_ext", "--nocolor"]);
+    assert_eq!(str, "test_files/upper_ext/UPPER.RS\n1:fn upper() {}\n");
+}
+
+#[test]
+fn glob_case_insensitive() {
+    let args = [
+        "sg",
+        "--rust",
+        "test",
+        "test_files/exclude",
+        "--exclude",
+        "*_TEST.rs",
+        "--exclude-dir",
+        "GENERATED",
+        "--nocolor",
+        "--nogroup",
+    ];
+
+    let mut args_case_insensitive = args.to_vec();
+    args_case_insensitive.push("--glob-case-insensitive");
+    let str = run_args(&args_case_insensitive);
+
+    // Order of files in a directory is not specified
+    let mut lines: Vec<&str> = str.lines().collect();
+    lines.sort_unstable();
+
+    assert_eq!(
+        lines,
+        vec![
+            "test_files/exclude/main.rs:1:fn test() {}",
+            "test_files/exclude/src/lib.rs:1:fn test() {}",
+        ]
+    );
+
+    // Globs are case sensitive by default (except on Windows)
+    if !cfg!(windows) {
+        let str = run_args(&args);
+        assert_eq!(str.lines().count(), 4);
+    }
+}
+
+#[test]
+fn exclude() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/exclude",
+        "--exclude",
+        "*_test.rs",
+        "--exclude-dir",
+        "generated/",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    // Order of files in a directory is not specified
+    let mut lines: Vec<&str> = str.lines().collect();
+    lines.sort_unstable();
+
+    assert_eq!(
+        lines,
+        vec![
+            "test_files/exclude/main.rs:1:fn test() {}",
+            "test_files/exclude/src/lib.rs:1:fn test() {}",
+        ]
+    );
+
+    // Globs are matched against paths relative to the searched directory
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/exclude",
+        "--exclude",
+        "src/*",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    assert_eq!(str, "test_files/exclude/main.rs:1:fn test() {}\n");
+}
+
+#[test]
+fn include_glob() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/exclude",
+        "test_files/mixed",
+        "-g",
+        "*_test.rs",
+        "--glob",
+        "a.*",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/exclude/src/lib_test.rs:1:fn test() {}\n\
+         test_files/mixed/a.rs:1:fn test() {}\n"
+    );
+
+    // Excluded files are skipped even when they match a `--glob`
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/exclude",
+        "-g",
+        "src/*",
+        "--exclude-dir",
+        "generated",
+        "--exclude",
+        "lib.rs",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    assert_eq!(str, "test_files/exclude/src/lib_test.rs:1:fn test() {}\n");
+}
+
+#[test]
+fn follow_symlinks() {
+    // Symlinks to directories are skipped by default
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/symlink/root",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    assert_eq!(str, "test_files/symlink/root/a.rs:1:fn test() {}\n");
+
+    // With `--follow`, `root/link/loop` is a cycle and skipped
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/symlink/root",
+        "--follow",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    let mut lines: Vec<&str> = str.lines().collect();
+    lines.sort_unstable();
+
+    assert_eq!(
+        lines,
+        vec![
+            "test_files/symlink/root/a.rs:1:fn test() {}",
+            "test_files/symlink/root/link/b.rs:1:fn test() {}",
+        ]
+    );
+}
+
+#[test]
+fn max_depth() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/exclude",
+        "--max-depth",
+        "0",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    assert_eq!(str, "test_files/exclude/main.rs:1:fn test() {}\n");
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/exclude",
+        "--max-depth",
+        "1",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    let mut lines: Vec<&str> = str.lines().collect();
+    lines.sort_unstable();
+
+    assert_eq!(
+        lines,
+        vec![
+            "test_files/exclude/main.rs:1:fn test() {}",
+            "test_files/exclude/src/lib.rs:1:fn test() {}",
+            "test_files/exclude/src/lib_test.rs:1:fn test() {}",
+        ]
+    );
+
+    // Files given as PATH are searched regardless of their depth
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/exclude/src/generated/gen.rs",
+        "--max-depth",
+        "0",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/exclude/src/generated/gen.rs:1:fn test() {}\n"
+    );
+}
+
+#[test]
+fn hidden() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/hidden",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    assert_eq!(str, "test_files/hidden/c.rs:1:fn test() {}\n");
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/hidden",
+        "--hidden",
+        "--nocolor",
+        "--nogroup",
+    ]);
+
+    let mut lines: Vec<&str> = str.lines().collect();
+    lines.sort_unstable();
+
+    assert_eq!(
+        lines,
+        vec![
+            "test_files/hidden/.b.rs:1:fn test() {}",
+            "test_files/hidden/.dir/a.rs:1:fn test() {}",
+            "test_files/hidden/c.rs:1:fn test() {}",
+        ]
+    );
+}
+
+#[test]
+fn binary() {
+    let str = run_args_no_match(&["sg", "--rust", "test", "test_files/binary", "--nocolor"]);
+    assert_eq!(str, "");
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/binary",
+        "--text",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/binary/binary.rs\n\
+         1:fn test() {}\n"
+    );
+}
+
+#[test]
+fn count_matches() {
+    // Total is printed to stderr, normal output is not affected
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "--count-matches",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         1:fn test() {\n"
+    );
+}
+
+#[test]
+fn progress() {
+    // Progress is printed to stderr, normal output is not affected
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "--progress",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         1:fn test() {\n"
+    );
+}
+
+#[test]
+fn context_separator() {
+    // Context of nearby matches are merged, a separator is printed between non-adjacent context
+    // lines
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "foo",
+        "test_files/context",
+        "--nocolor",
+        "-C",
+        "3",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/context/context.rs\n\
+         1-fn main() {\n\
+         2:    foo();\n\
+         3-    a();\n\
+         4:    foo();\n\
+         5-    b();\n\
+         6-    c();\n\
+         7-    d();\n\
+         --\n\
+         11-    h();\n\
+         12-    i();\n\
+         13-    j();\n\
+         14:    foo();\n\
+         15-    k();\n\
+         16-}\n"
+    );
+
+    // No separator when context lines are adjacent
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "foo",
+        "test_files/context",
+        "--nocolor",
+        "-A",
+        "6",
+        "-B",
+        "3",
+    ]);
+
+    assert!(!str.contains("--"));
+}
+
+#[test]
+fn heading() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple/simple.rs",
+        "test_files/mixed/a.rs",
+        "--no-heading",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:1:fn test() {\n\
+         \n\
+         test_files/mixed/a.rs:1:fn test() {}\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple/simple.rs",
+        "test_files/mixed/a.rs",
+        "--nogroup",
+        "--heading",
+        "--nocolor",
+    ]);
+
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         1:fn test() {\n\
+         test_files/mixed/a.rs\n\
+         1:fn test() {}\n"
+    );
+}
+
+#[test]
+fn list_node_kinds() {
+    let str = run_args_stdin(
+        &["sg", "--rust", "--list-node-kinds", "-", "--nocolor"],
+        "fn f() {}\n",
+    );
+
+    assert_eq!(
+        str,
+        "<stdin>\n      \
+         1 block\n      \
+         1 function_item\n      \
+         1 identifier\n      \
+         1 parameters\n      \
+         1 source_file\n      \
+         1 \"(\"\n      \
+         1 \")\"\n      \
+         1 \"fn\"\n      \
+         1 \"{\"\n      \
+         1 \"}\"\n"
+    );
+}
+
+#[test]
+fn debug_ast() {
+    let str = run_args_stdin(
+        &["sg", "--rust", "--debug-ast", "-", "--nocolor"],
+        "fn f() {\n    g(1);\n}\n",
+    );
+
+    assert_eq!(
+        str,
+        "<stdin>\n\
+         source_file 1:1-4:1\n  \
+           function_item 1:1-3:2\n    \
+             name: identifier 1:4-1:5\n    \
+             parameters: parameters 1:5-1:7\n    \
+             body: block 1:8-3:2\n      \
+               expression_statement 2:5-2:10\n        \
+                 call_expression 2:5-2:9\n          \
+                   function: identifier 2:5-2:6\n          \
+                   arguments: arguments 2:6-2:9\n            \
+                     integer_literal 2:7-2:8\n"
+    );
+}
+
+#[test]
+fn query_zero_width_capture() {
+    // Missing `;` is a zero-width node
+    let str = run_args_stdin(
+        &["sg", "--rust", "--qs", "(let_declaration \";\" @semi)", "-"],
+        "fn f() { let x = 1 }\n",
+    );
+    assert_eq!(
+        str,
+        "\u{1b}[1;32m<stdin>\u{1b}[0m\n\
+         \u{1b}[1;33m1\u{1b}[0m:fn f() { let x = 1 }\n"
+    );
+
+    // Root node of a file with only empty lines starts after the last line
+    let (ret, str) = run_args_ret(
+        &[
+            "sg",
+            "--rust",
+            "--qs",
+            "(source_file) @file",
+            "-",
+            "--nocolor",
+        ],
+        "\n\n",
+    );
+    assert_eq!(ret, 1);
+    assert_eq!(str, "");
+}
+
+#[test]
+fn ignore_case_non_ascii() {
+    // Lowercase of 'İ' is longer than 'İ' in UTF-8
+    let str = run_args_stdin(
+        &["sg", "--rust", "İzmir", "-i", "--column", "-"],
+        "fn f() { let İzmir_İzmir = 1; }\n",
+    );
+    assert_eq!(
+        str,
+        "\u{1b}[1;32m<stdin>\u{1b}[0m\n\
+         \u{1b}[1;33m1\u{1b}[0m:14:fn f() { let \
+         \u{1b}[43;30mİzmir\u{1b}[0m_\u{1b}[43;30mİzmir\u{1b}[0m = 1; }\n"
+    );
+}
+
+#[test]
+fn ignore_case_non_ascii_capture() {
+    let str = run_args_stdin(
+        &[
+            "sg",
+            "--rust",
+            "--qs",
+            "(let_declaration pattern: (identifier) @x)",
+            "--capture",
+            "x=straße",
+            "-",
+            "--nocolor",
+        ],
+        "fn f() {\n    let STRAẞE = 1;\n    let strasse = 2;\n}\n",
+    );
+    assert_eq!(str, "<stdin>\n2:    let STRAẞE = 1;\n");
+}
+
+#[test]
+fn exit_code() {
+    let args = ["sg", "--rust", "test", "test_files/simple", "--nocolor"];
+    assert_eq!(run_args_ret(&args, "").0, 0);
+
+    let args = ["sg", "--rust", "wow", "test_files/simple", "--nocolor"];
+    assert_eq!(run_args_ret(&args, "").0, 1);
+
+    // Invalid arguments
+    let args = ["sg", "--rust", "test", "--no-such-flag"];
+    assert_eq!(run_args_ret(&args, "").0, 2);
+
+    let (ret, str) = run_args_ret(&["sg", "--help"], "");
+    assert_eq!(ret, 0);
+    assert!(str.starts_with("sg"));
+}
+
+#[test]
+fn strict() {
+    let args = [
+        "sg",
+        "--rust",
+        "test",
+        "test_files/strict/valid.rs",
+        "test_files/strict/invalid.rs",
+        "--nocolor",
+    ];
+
+    // Files that can't be read are skipped
+    let (ret, str) = run_args_ret(&args, "");
+    assert_eq!(ret, 0);
+    assert_eq!(str, "test_files/strict/valid.rs\n1:fn test() {}\n");
+
+    // With `--strict` they're still skipped, but the exit code is 2
+    let args = [&args[..], &["--strict"]].concat();
+    let (ret, str) = run_args_ret(&args, "");
+    assert_eq!(ret, 2);
+    assert_eq!(str, "test_files/strict/valid.rs\n1:fn test() {}\n");
+}
+
+#[test]
+fn sort_path() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/sort",
+        "--sort",
+        "path",
+        "-l",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/sort/a.rs\n\
+         test_files/sort/b/b.rs\n\
+         test_files/sort/c.rs\n"
+    );
+}
+
+#[test]
+fn match_order() {
+    // Matches are reported in source order, including matches in nested literals
+    static INPUT: &str = "let x = `x ${x} ${`x`}`; // x\nx(`${x}`);\n";
+    let args = [
+        "sg",
+        "--js",
+        "x",
+        "-k",
+        "string,identifier,comment",
+        "--vimgrep",
+        "-",
+    ];
+
+    let str = run_args_stdin(&args, INPUT);
+    assert_eq!(
+        str,
+        "<stdin>:1:5:let x = `x ${x} ${`x`}`; // x\n\
+         <stdin>:1:10:let x = `x ${x} ${`x`}`; // x\n\
+         <stdin>:1:14:let x = `x ${x} ${`x`}`; // x\n\
+         <stdin>:1:20:let x = `x ${x} ${`x`}`; // x\n\
+         <stdin>:1:29:let x = `x ${x} ${`x`}`; // x\n\
+         <stdin>:2:1:x(`${x}`);\n\
+         <stdin>:2:6:x(`${x}`);\n"
+    );
+
+    // `--max-count` reports the first matches in source order
+    let str = run_args_stdin(&[&args[..], &["-m", "2"]].concat(), INPUT);
+    assert_eq!(
+        str,
+        "<stdin>:1:5:let x = `x ${x} ${`x`}`; // x\n\
+         <stdin>:1:10:let x = `x ${x} ${`x`}`; // x\n"
+    );
+}
+
+#[test]
+fn context_symbol() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "TODO",
+        "-k",
+        "comment",
+        "--context-symbol",
+        "test_files/context_symbol",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/context_symbol/symbols.rs\n\
+         1:// TODO: top level\n\
+         7:parse:            // TODO: parse\n\
+         11:parser:    // TODO: in module\n\
+         16:main:        // TODO: in closure\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "parse",
+        "--context-symbol",
+        "test_files/context_symbol",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/context_symbol/symbols.rs:2:5:parser:mod parser {\n\
+         test_files/context_symbol/symbols.rs:3:12:parser:    struct Parser;\n\
+         test_files/context_symbol/symbols.rs:5:10:Parser:    impl Parser {\n\
+         test_files/context_symbol/symbols.rs:6:12:parse:        fn parse(&self) {\n"
+    );
+}
+
+#[test]
+fn context_node() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "second",
+        "--context-node",
+        "call_expression,macro_invocation",
+        "test_files/context_node",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/context_node/calls.rs\n\
+         2-    let sum = add(\n\
+         3-        first,\n\
+         4:        second,\n\
+         5-    );\n\
+         --\n\
+         8-    println!(\n\
+         9-        \"{}\",\n\
+         10:        second\n\
+         11-    );\n"
+    );
+
+    // Lines of the node are printed in addition to the lines printed with `-A`, `-B`
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "first",
+        "--context-node",
+        "call_expression",
+        "-A",
+        "3",
+        "test_files/context_node",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/context_node/calls.rs\n\
+         2-    let sum = add(\n\
+         3:        first,\n\
+         4-        second,\n\
+         5-    );\n\
+         6-    let x = 1;\n"
+    );
+}
+
+#[test]
+fn string_quotes() {
+    // Columns are of the match, after the opening quote
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "-k",
+        "string",
+        "test_files/string_quote",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/string_quote/quote.rs:2:14:    let s = \"test\";\n\
+         test_files/string_quote/quote.rs:3:16:    let t = \"a test\";\n"
+    );
+
+    // Quotes are searched, and are a part of the match when the pattern has them
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "\"test",
+        "-k",
+        "string",
+        "test_files/string_quote",
+        "--json",
+    ]);
+    assert_eq!(
+        str,
+        "{\"byte_offset\":24,\"column\":13,\"line\":2,\"line_text\":\"    let s = \\\"test\\\";\",\
+         \"match_text\":\"\\\"test\",\"node_kind\":\"string_literal\",\
+         \"path\":\"test_files/string_quote/quote.rs\"}\n"
+    );
+
+    // Replacements replace the match only, quotes are kept unless the pattern has them
+    let input = std::fs::read_to_string("test_files/string_quote/quote.rs").unwrap();
+    let str = run_args_stdin(
+        &[
+            "sg",
+            "--rust",
+            "test",
+            "-k",
+            "string",
+            "--replace",
+            "done",
+            "-",
+        ],
+        &input,
+    );
+    assert_eq!(
+        str,
+        "fn main() {\n    let s = \"done\";\n    let t = \"a done\";\n}\n"
+    );
+
+    let str = run_args_stdin(
+        &[
+            "sg",
+            "--rust",
+            "\"test\"",
+            "-k",
+            "string",
+            "--replace",
+            "\"done\"",
+            "-",
+        ],
+        &input,
+    );
+    assert_eq!(
+        str,
+        "fn main() {\n    let s = \"done\";\n    let t = \"a test\";\n}\n"
+    );
+}
+
+#[test]
+fn raw_string() {
+    // Raw strings, byte strings, and raw byte strings are strings
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "-k",
+        "string",
+        "test_files/raw_string",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/raw_string/raw.rs\n\
+         2:    let a = r\"test\";\n\
+         3:    let b = r#\"a \"test\"\"#;\n\
+         4:    let c = b\"test\";\n\
+         5:    let d = br\"test\";\n"
+    );
+
+    // And not identifiers
+    let str = run_args(&["sg", "--rust", "test", "test_files/raw_string", "--nocolor"]);
+    assert_eq!(str, "test_files/raw_string/raw.rs\n6:    let test = 1;\n");
+}
+
+#[test]
+fn doc_comment() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "deprecated",
+        "-k",
+        "doc_comment",
+        "test_files/doc_comment",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/doc_comment/doc.rs\n\
+         1://! deprecated module\n\
+         2:/// deprecated function\n\
+         5:/** deprecated block */\n\
+         6:/*! deprecated inner block */\n"
+    );
+
+    // `comment` includes doc comments
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "deprecated",
+        "-k",
+        "comment",
+        "test_files/doc_comment",
+        "--nocolor",
+        "-c",
+    ]);
+    assert_eq!(str, "test_files/doc_comment/doc.rs: 7\n");
+}
+
+#[test]
+fn count_per_line() {
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "-k",
+        "string,identifier",
+        "test_files/simple",
+        "--count-per-line",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:1: 1\n\
+         test_files/simple/simple.rs:2: 2\n\
+         test_files/simple/simple.rs:3: 1\n"
+    );
+}
+
+#[test]
+fn cache() {
+    // A file searched twice is parsed once, and reported the same way both times
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        "test_files/strict/valid.rs",
+        "test_files/strict/valid.rs",
+        "--cache",
+        "-j",
+        "1",
+        "--vimgrep",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/strict/valid.rs:1:4:fn test() {}\n\
+         test_files/strict/valid.rs:1:4:fn test() {}\n"
+    );
+}
+
+#[test]
+fn watch_stdin() {
+    // stdin can't be searched again
+    let (ret, str) = run_args_ret(&["sg", "--rust", "test", "--watch", "-"], "fn test() {}");
+    assert_eq!(ret, 2);
+    assert_eq!(str, "");
+}
+
+#[test]
+fn list_files() {
+    // Files are listed without searching, so the first positional argument is a path
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--files",
+        "test_files/sort",
+        "--sort",
+        "path",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/sort/a.rs\n\
+         test_files/sort/b/b.rs\n\
+         test_files/sort/c.rs\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "--files",
+        "test_files/sort",
+        "--exclude-dir",
+        "b",
+        "--sort",
+        "path",
+        "--nocolor",
+    ]);
+    assert_eq!(str, "test_files/sort/a.rs\ntest_files/sort/c.rs\n");
+}
+
+#[test]
+fn parser_dir() {
+    // Libraries that can't be loaded are skipped, with `--parser-dir` only files of the searched
+    // languages are searched
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "fn",
+        "--parser-dir",
+        "test_files/dynamic",
+        "-k",
+        "keyword",
+        "test_files/keyword",
+        "--vimgrep",
+    ]);
+    assert_eq!(str, "test_files/keyword/keyword.rs:1:1:fn fn_name() {\n");
+
+    let (ret, str) = run_args_ret(
+        &[
+            "sg",
+            "fn",
+            "--parser-dir",
+            "test_files/dynamic",
+            "test_files/keyword",
+        ],
+        "",
+    );
+    assert_eq!(ret, 2);
+    assert_eq!(str, "");
+
+    let (ret, str) = run_args_ret(
+        &[
+            "sg",
+            "--rust",
+            "fn",
+            "--parser-dir",
+            "test_files/dynamic/missing",
+        ],
+        "",
+    );
+    assert_eq!(ret, 2);
+    assert_eq!(str, "");
+}
+
+#[test]
+fn dump() {
+    // Without a pattern each token is one match, first positional argument is a path
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "-k",
+        "string,comment",
+        "--dump",
+        "test_files/simple",
+        "--vimgrep",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:2:13:    let s = \"testtest\";\n\
+         test_files/simple/simple.rs:3:13:    let s = \"test\";\n\
+         test_files/simple/simple.rs:4:5:    // wow\n"
+    );
+
+    // `--kind` is required
+    let (ret, _) = run_args_ret(&["sg", "--rust", "--dump", "test_files/simple"], "");
+    assert_eq!(ret, 2);
+}
+
+#[test]
+fn keyword() {
+    let args = ["sg", "--rust", "--vimgrep", "--nocolor"];
+
+    // Identifiers and keywords are searched by default
+    let str = run_args(&[&args[..], &["fn", "test_files/keyword"]].concat());
+    assert_eq!(
+        str,
+        "test_files/keyword/keyword.rs:1:1:fn fn_name() {\n\
+         test_files/keyword/keyword.rs:1:4:fn fn_name() {\n"
+    );
+
+    let str = run_args(&[&args[..], &["fn", "test_files/keyword", "-k", "identifier"]].concat());
+    assert_eq!(str, "test_files/keyword/keyword.rs:1:4:fn fn_name() {\n");
+
+    let str = run_args(&[&args[..], &["let", "test_files/keyword", "-k", "keyword"]].concat());
+    assert_eq!(str, "test_files/keyword/keyword.rs:2:5:    let let_ = 1;\n");
+}
+
+#[test]
+fn punctuation() {
+    // Punctuation and operators are not searched as identifiers
+    run_args_no_match(&["sg", "--rust", "(", "test_files/keyword"]);
+    run_args_no_match(&["sg", "--rust", "-r", "=", "test_files/keyword"]);
+
+    // Number literals are
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "1",
+        "test_files/keyword",
+        "-k",
+        "identifier",
+        "--vimgrep",
+        "--nocolor",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/keyword/keyword.rs:2:16:    let let_ = 1;\n"
+    );
+}
+
+#[test]
+fn no_line_number() {
+    let args = [
+        "sg",
+        "--rust",
+        "test",
+        "test_files/simple",
+        "--nocolor",
+        "-N",
+    ];
+
+    let str = run_args(&args);
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         fn test() {\n"
+    );
+
+    let str = run_args(&[&args[..], &["--nogroup", "-A", "1"]].concat());
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs:fn test() {\n\
+         test_files/simple/simple.rs-    let s = \"testtest\";\n"
+    );
+
+    // `-n` after `-N` prints line numbers
+    let str = run_args(&[&args[..], &["-n"]].concat());
+    assert_eq!(
+        str,
+        "test_files/simple/simple.rs\n\
+         1:fn test() {\n"
+    );
+}
+
+#[test]
+fn flush() {
+    // Output is flushed before `run` returns, and ends with the newline after the last match
+    let mut stdout = std::io::BufWriter::new(vec![]);
+    let ret = run(
+        &mut stdout,
+        &mut std::io::sink(),
+        &mut "".as_bytes(),
+        ["sg", "--rust", "test", "test_files/simple", "--nocolor"]
+            .iter()
+            .map(OsString::from),
+        true,
+    );
+    assert_eq!(ret, 0);
+    assert!(stdout.buffer().is_empty());
+    assert_eq!(
+        String::from_utf8(stdout.get_ref().clone()).unwrap(),
+        "test_files/simple/simple.rs\n1:fn test() {\n"
+    );
+}
+
+#[test]
+fn encoding() {
+    // BOMs are not a part of the contents, UTF-16 files are searched as UTF-8. Columns and
+    // matched texts are of the decoded contents.
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "t[eë]st",
+        "-r",
+        "-k",
+        "identifier,keyword,string",
+        "test_files/encoding",
+        "--sort",
+        "path",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/encoding/bom.rs:1:4:fn test() {\n\
+         test_files/encoding/bom.rs:2:14:    let s = \"tëst\";\n\
+         test_files/encoding/utf16be.rs:1:4:fn test() {\n\
+         test_files/encoding/utf16be.rs:2:14:    let s = \"tëst\";\n\
+         test_files/encoding/utf16le.rs:1:4:fn test() {\n\
+         test_files/encoding/utf16le.rs:2:14:    let s = \"tëst\";\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "fn",
+        "-k",
+        "keyword",
+        "test_files/encoding/bom.rs",
+        "--vimgrep",
+    ]);
+    assert_eq!(str, "test_files/encoding/bom.rs:1:1:fn test() {\n");
+}
+
+#[test]
+fn encoding_option() {
+    // Files without a BOM are decoded with `--encoding`
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "tést",
+        "-k",
+        "string",
+        "--encoding",
+        "latin1",
+        "test_files/latin1/latin1.rs",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/latin1/latin1.rs:2:14:    let s = \"tést\";\n"
+    );
+
+    // Files with a BOM are decoded with the encoding of the BOM
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "tëst",
+        "-k",
+        "string",
+        "--encoding",
+        "latin1",
+        "test_files/encoding/utf16le.rs",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/encoding/utf16le.rs:2:14:    let s = \"tëst\";\n"
+    );
+
+    let (ret, str) = run_args_ret(&["sg", "--rust", "test", "--encoding", "latin-42"], "");
+    assert_eq!(ret, 2);
+    assert_eq!(str, "");
+
+    // Replaced files are written back in the encoding
+    let dir = std::env::temp_dir().join(format!("sg_encoding_option_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("latin1.rs");
+    std::fs::copy("test_files/latin1/latin1.rs", &path).unwrap();
+
+    run_args(&[
+        "sg",
+        "--rust",
+        "s",
+        "--encoding",
+        "latin1",
+        path.to_str().unwrap(),
+        "--replace",
+        "é",
+    ]);
+    assert_eq!(
+        std::fs::read(&path).unwrap(),
+        b"fn test() {\n    let \xE9 = \"t\xE9st\";\n}\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn text_fallback() {
+    // Tokens are searched separately, so patterns spanning multiple tokens don't match
+    run_args_no_match(&[
+        "sg",
+        "--rust",
+        "b.c",
+        "test_files/text_fallback/text_fallback.rs",
+    ]);
+
+    // Lines are searched for matches spanning multiple tokens. Comments and literals are not
+    // searched unless selected with `-k`.
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "b.c",
+        "--text-fallback",
+        "test_files/text_fallback/text_fallback.rs",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/text_fallback/text_fallback.rs:2:13:    let a = b.c;\n\
+         test_files/text_fallback/text_fallback.rs:5:13:    f(b .c, b.c());\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "b.c",
+        "-k",
+        "identifier,string",
+        "--text-fallback",
+        "test_files/text_fallback/text_fallback.rs",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/text_fallback/text_fallback.rs:2:13:    let a = b.c;\n\
+         test_files/text_fallback/text_fallback.rs:4:14:    let s = \"b.c\";\n\
+         test_files/text_fallback/text_fallback.rs:5:13:    f(b .c, b.c());\n"
+    );
+
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "b\\s*\\.c",
+        "-r",
+        "--text-fallback",
+        "test_files/text_fallback/text_fallback.rs",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/text_fallback/text_fallback.rs:2:13:    let a = b.c;\n\
+         test_files/text_fallback/text_fallback.rs:5:7:    f(b .c, b.c());\n\
+         test_files/text_fallback/text_fallback.rs:5:13:    f(b .c, b.c());\n"
+    );
+
+    // With `-m`, line matches and token matches are limited together, in source order
+    let str = run_args(&[
+        "sg",
+        "--rust",
+        "b.c",
+        "-k",
+        "identifier,string",
+        "--text-fallback",
+        "-m",
+        "2",
+        "test_files/text_fallback/text_fallback.rs",
+        "--vimgrep",
+    ]);
+    assert_eq!(
+        str,
+        "test_files/text_fallback/text_fallback.rs:2:13:    let a = b.c;\n\
+         test_files/text_fallback/text_fallback.rs:4:14:    let s = \"b.c\";\n"
+    );
+}
+
+#[test]
+fn replace_encoding() {
+    // Files are written back in their encodings
+    let dir = std::env::temp_dir().join(format!("sg_replace_encoding_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in ["bom.rs", "utf16le.rs", "utf16be.rs"] {
+        std::fs::copy(format!("test_files/encoding/{}", name), dir.join(name)).unwrap();
+    }
+
+    run_args(&[
+        "sg",
+        "--rust",
+        "test",
+        dir.to_str().unwrap(),
+        "--replace",
+        "check",
+    ]);
+
+    for name in ["bom.rs", "utf16le.rs", "utf16be.rs"] {
+        let old_bytes = std::fs::read(format!("test_files/encoding/{}", name)).unwrap();
+        let new_bytes = std::fs::read(dir.join(name)).unwrap();
+        let encoding = crate::encoding::Encoding::detect(&old_bytes, None);
+        assert_eq!(
+            crate::encoding::Encoding::detect(&new_bytes, None),
+            encoding
+        );
+        assert_eq!(
+            encoding.decode(new_bytes).unwrap(),
+            encoding.decode(old_bytes).unwrap().replace("test", "check")
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,80 @@
+//! Searching again when the searched files change (`--watch`)
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::{collect_files, search_files, Cfg, Stats};
+
+/// After a change, how long to wait for more changes before searching again. Editors and tools
+/// like `git checkout` write multiple files at once, this avoids searching after each one.
+const SETTLE_TIME: Duration = Duration::from_millis(100);
+
+/// Clear the screen and move the cursor to the top left corner
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Watch `paths` and search them again when a file in them changes. Syntax trees are cached as
+/// with `--cache`, so most files that were not modified are not parsed again.
+///
+/// Only returns on errors, with the exit code.
+pub(crate) fn watch<W: Write>(
+    stdout: &mut W,
+    pool: &rayon::ThreadPool,
+    paths: &[PathBuf],
+    cfg: &Cfg,
+    ignores: &[String],
+) -> i32 {
+    let (sender, receiver) = mpsc::channel::<notify::Result<notify::Event>>();
+
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            eprintln!("Unable to watch files: {}", err);
+            return 2;
+        }
+    };
+
+    for path in paths {
+        let mode = if path.is_dir() {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        if let Err(err) = watcher.watch(path, mode) {
+            eprintln!("Unable to watch {}: {}", path.to_string_lossy(), err);
+            return 2;
+        }
+    }
+
+    loop {
+        if !wait_for_change(&receiver) {
+            eprintln!("File watcher stopped");
+            return 2;
+        }
+        while receiver.recv_timeout(SETTLE_TIME).is_ok() {}
+
+        let _ = write!(stdout, "{}", CLEAR_SCREEN);
+
+        // Walk the paths again, as files may have been added or removed
+        let stats = Stats::default();
+        let files = collect_files(paths, cfg, ignores, &stats, None);
+        search_files(stdout, pool, &files, cfg, &stats, None, "");
+        let _ = stdout.flush();
+    }
+}
+
+/// Wait until a file is modified, created, or removed. Returns `false` when the watcher stops.
+fn wait_for_change(receiver: &mpsc::Receiver<notify::Result<notify::Event>>) -> bool {
+    loop {
+        match receiver.recv() {
+            // Reading files when searching generates access events, don't search again for those
+            Ok(Ok(event)) if event.kind.is_access() => {}
+            Ok(Ok(_)) => return true,
+            Ok(Err(err)) => eprintln!("Error while watching files: {}", err),
+            Err(_) => return false,
+        }
+    }
+}
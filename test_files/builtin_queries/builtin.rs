@@ -0,0 +1,13 @@
+fn main() {
+    // TODO: handle errors
+    let x = unsafe { read() };
+    if x == 0 {
+        panic!("zero");
+    }
+    /* FIXME */
+    println!("{}", x);
+}
+
+unsafe fn read() -> i32 {
+    unimplemented!()
+}
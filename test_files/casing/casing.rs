@@ -0,0 +1,2 @@
+fn foo() {}
+fn Foo() {}
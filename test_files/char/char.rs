@@ -0,0 +1,4 @@
+fn main() {
+    let c = 'x';
+    let x = "x";
+}
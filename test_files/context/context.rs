@@ -0,0 +1,16 @@
+fn main() {
+    foo();
+    a();
+    foo();
+    b();
+    c();
+    d();
+    e();
+    f();
+    g();
+    h();
+    i();
+    j();
+    foo();
+    k();
+}
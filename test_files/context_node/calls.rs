@@ -0,0 +1,12 @@
+fn main() {
+    let sum = add(
+        first,
+        second,
+    );
+    let x = 1;
+
+    println!(
+        "{}",
+        second
+    );
+}
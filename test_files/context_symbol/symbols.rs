@@ -0,0 +1,18 @@
+// TODO: top level
+mod parser {
+    struct Parser;
+
+    impl Parser {
+        fn parse(&self) {
+            // TODO: parse
+        }
+    }
+
+    // TODO: in module
+}
+
+fn main() {
+    let f = || {
+        // TODO: in closure
+    };
+}
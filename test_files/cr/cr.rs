@@ -0,0 +1,2 @@
+fn main() {}
+/* atest */
\ No newline at end of file
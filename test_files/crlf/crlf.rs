@@ -0,0 +1,4 @@
+fn foo() {
+    let test = 1;
+    bar(test, "test");
+}
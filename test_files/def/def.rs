@@ -0,0 +1,13 @@
+trait Search {
+    fn search(&self);
+}
+
+fn search_all(items: &[&dyn Search]) {
+    for item in items {
+        item.search();
+    }
+}
+
+impl Search for () {
+    fn search(&self) {}
+}
@@ -0,0 +1,8 @@
+//! deprecated module
+/// deprecated function
+//// deprecated, not a doc comment
+// deprecated, regular comment
+/** deprecated block */
+/*! deprecated inner block */
+/* deprecated regular block */
+fn deprecated() {}
@@ -0,0 +1,7 @@
+fn parse_config() {}
+
+fn print_config_file() {}
+
+fn main() {
+    parse_config();
+}
@@ -0,0 +1,6 @@
+package main
+
+// greet prints a greeting
+func greet(name string) {
+	fmt.Println("greeting", name, `raw greeting`, 'g')
+}
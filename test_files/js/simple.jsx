@@ -0,0 +1 @@
+const hello = "hello";
@@ -0,0 +1,3 @@
+fn fn_name() {
+    let let_ = 1;
+}
@@ -0,0 +1,3 @@
+fn test() {
+    let s = "tst";
+}
@@ -0,0 +1,5 @@
+fn main() {
+    let x = 42;
+    let y = 42.5;
+    // 42
+}
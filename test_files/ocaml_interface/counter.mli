@@ -0,0 +1,3 @@
+val counter : int ref
+val incr_counter : unit -> unit
+val reset : unit -> unit
@@ -0,0 +1,4 @@
+def test():
+    # test
+    s = "testing"
+    return s
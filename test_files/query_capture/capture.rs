@@ -0,0 +1,5 @@
+fn main() {
+    add(1, 2);
+    sub(3, 4);
+    mul(5, 6);
+}
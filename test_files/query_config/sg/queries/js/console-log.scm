@@ -0,0 +1,2 @@
+; Calls of console.log
+(call_expression function: (member_expression object: (identifier) @obj property: (property_identifier) @prop) (#eq? @obj "console") (#eq? @prop "log"))
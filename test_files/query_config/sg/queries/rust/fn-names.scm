@@ -0,0 +1,2 @@
+; Names of functions
+(function_item name: (identifier) @name)
@@ -0,0 +1,7 @@
+fn main() {
+    let a = r"test";
+    let b = r#"a "test""#;
+    let c = b"test";
+    let d = br"test";
+    let test = 1;
+}
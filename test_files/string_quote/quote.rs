@@ -0,0 +1,4 @@
+fn main() {
+    let s = "test";
+    let t = "a test";
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let a = b.c;
+    // b.c in a comment
+    let s = "b.c";
+    f(b .c, b.c());
+}
@@ -0,0 +1,3 @@
+function Greet(props: { name: string }) {
+    return <div className="greeting">{props.name}</div>;
+}